@@ -8,19 +8,27 @@
 //!
 //! Note: `Drop` cannot be async. Always prefer calling `close().await` for
 //! deterministic cleanup; `Drop` provides best-effort idle cleanup only.
+//!
+//! All timing (timeouts, lifetimes, maintenance ticks) reads the Tokio clock,
+//! so tests can run under `#[tokio::test(start_paused = true)]`, move time with
+//! `tokio::time::advance`, and run a maintenance pass with
+//! `Pool::run_maintenance_now()` (behind the `test-util` feature).
 
 pub mod traits;
 
 use crate::error::{Result, ShadowcatError};
-use std::collections::VecDeque;
+use futures::StreamExt;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::future::Future;
+use std::hash::Hash;
 use std::pin::Pin;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     Arc,
 };
-use std::time::{Duration, Instant};
-use tokio::sync::{Mutex, Semaphore};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{broadcast, mpsc, Mutex, Semaphore};
+use tokio::time::Instant;
 use tracing::{debug, trace, warn};
 
 use traits::PoolableResource;
@@ -29,20 +37,213 @@ use traits::PoolableResource;
 #[derive(Debug, Clone)]
 pub struct PoolOptions {
     pub max_connections: usize,
+    /// Number of resources maintenance keeps open (idle or checked out).
+    /// Requires a pool factory; clamped to `max_connections`.
+    pub min_connections: usize,
     pub acquire_timeout: Duration,
     pub idle_timeout: Option<Duration>,
     pub max_lifetime: Option<Duration>,
     pub health_check_interval: Duration,
+    /// Which idle resource `acquire` hands out first.
+    pub reuse_strategy: ReuseStrategy,
+    /// How long a waiter can be passed over before it is served as
+    /// [`Priority::High`], so low-priority acquires are never starved outright.
+    pub starvation_timeout: Duration,
+    /// When `is_healthy()` runs on release, reuse and maintenance.
+    pub health_check: HealthCheckPolicy,
+    /// A health check that takes longer than this counts as a failure.
+    pub health_check_timeout: Option<Duration>,
+    /// How many idle resources maintenance health-checks at once, and how
+    /// many returned connections it releases at once.
+    pub health_check_concurrency: usize,
+    /// Warn about connections held longer than this. Checked on each maintenance
+    /// tick; the warning includes the backtrace captured at checkout when
+    /// `RUST_BACKTRACE` is set.
+    pub leak_timeout: Option<Duration>,
+    /// Retry failed creations with the pool's factory, within `acquire_timeout`.
+    /// Closures passed to `acquire_with` run once and are never retried.
+    pub create_retry: Option<RetryPolicy>,
+    /// Stop creating resources while the upstream keeps failing.
+    pub circuit_breaker: Option<CircuitBreakerOptions>,
+    /// When maintenance finds an idle resource past `max_lifetime`, create its
+    /// replacement with the pool's factory before closing it, so there is no
+    /// gap in warm capacity. The pool briefly holds one extra resource.
+    pub replace_before_expire: bool,
+}
+
+/// Settings for the pool's circuit breaker.
+///
+/// Factory errors and failed health checks count as failures; a successful
+/// creation counts as a success. After `failure_threshold` consecutive failures
+/// the breaker opens for `cool_down`: acquires still get healthy idle resources,
+/// but any that would need a new one fail immediately. It then lets
+/// `half_open_probes` creations through, closing once they all succeed and
+/// reopening on the first failure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitBreakerOptions {
+    pub failure_threshold: u32,
+    pub cool_down: Duration,
+    pub half_open_probes: u32,
+}
+
+impl Default for CircuitBreakerOptions {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(10),
+            half_open_probes: 1,
+        }
+    }
+}
+
+/// State of the pool's circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Acquires proceed normally.
+    Closed,
+    /// Acquires fail fast until the cool-down ends.
+    Open,
+    /// A limited number of probe acquires are allowed through.
+    HalfOpen,
+}
+
+/// Exponential backoff for factory retries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on each later retry.
+    pub backoff: Duration,
+    /// Randomize each delay to between half and all of its nominal value, so
+    /// callers failing together don't retry in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_millis(50),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay after failed attempt number `attempt` (1-based).
+    fn delay(&self, attempt: u32) -> Duration {
+        let nominal = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        if !self.jitter {
+            return nominal;
+        }
+        use std::hash::{BuildHasher, Hasher};
+        let random = std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish();
+        let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+        nominal.mul_f64(fraction)
+    }
+}
+
+/// Decides whether a resource gets an `is_healthy()` check before it is
+/// requeued (release), handed out (reuse) or kept (maintenance).
+///
+/// Resources that skip the check are treated as healthy.
+#[derive(Clone, Default)]
+pub enum HealthCheckPolicy {
+    /// Never call `is_healthy()`; rely on lifetimes and hooks.
+    Never,
+    /// Only check resources that have been idle at least this long. Releases
+    /// are never checked, since the resource was just in use.
+    OnIdleOlderThan(Duration),
+    /// Check on every release, reuse and maintenance pass.
+    #[default]
+    Always,
+    /// Ask the callback whether to check, given the resource's age and idle time.
+    Custom(HealthCheckFn),
+}
+
+/// Callback for [`HealthCheckPolicy::Custom`]; resolves to `true` to run `is_healthy()`.
+pub type HealthCheckFn =
+    Arc<dyn Fn(PoolConnectionMetadata) -> Pin<Box<dyn Future<Output = bool> + Send>> + Send + Sync>;
+
+impl std::fmt::Debug for HealthCheckPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Never => f.write_str("Never"),
+            Self::OnIdleOlderThan(d) => f.debug_tuple("OnIdleOlderThan").field(d).finish(),
+            Self::Always => f.write_str("Always"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl HealthCheckPolicy {
+    async fn should_check(&self, meta: PoolConnectionMetadata) -> bool {
+        match self {
+            Self::Never => false,
+            Self::OnIdleOlderThan(min_idle) => meta.idle_for >= *min_idle,
+            Self::Always => true,
+            Self::Custom(cb) => cb(meta).await,
+        }
+    }
+
+    /// Whether a just-released resource is returned without a check.
+    fn skips_release(&self) -> bool {
+        match self {
+            Self::Never => true,
+            Self::OnIdleOlderThan(min_idle) => !min_idle.is_zero(),
+            Self::Always | Self::Custom(_) => false,
+        }
+    }
+}
+
+/// Priority class of an acquire that has to wait for capacity.
+///
+/// Waiters are served highest class first, FIFO within a class.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Background work such as replay traffic.
+    Low,
+    #[default]
+    Normal,
+    /// Interactive requests.
+    High,
+}
+
+/// Order in which idle resources are reused.
+///
+/// Released resources always go to the back of the idle queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReuseStrategy {
+    /// Reuse the longest-idle resource first, spreading load across all idle resources.
+    #[default]
+    Fifo,
+    /// Reuse the most recently released resource first, letting idle timeouts
+    /// shrink the pool under low load.
+    Lifo,
 }
 
 impl Default for PoolOptions {
     fn default() -> Self {
         Self {
             max_connections: 10,
+            min_connections: 0,
             acquire_timeout: Duration::from_secs(5),
             idle_timeout: Some(Duration::from_secs(300)),
             max_lifetime: Some(Duration::from_secs(3600)),
             health_check_interval: Duration::from_secs(30),
+            reuse_strategy: ReuseStrategy::default(),
+            starvation_timeout: Duration::from_secs(1),
+            health_check: HealthCheckPolicy::default(),
+            health_check_timeout: Some(Duration::from_secs(5)),
+            health_check_concurrency: 8,
+            leak_timeout: None,
+            create_retry: None,
+            circuit_breaker: None,
+            replace_before_expire: false,
         }
     }
 }
@@ -51,963 +252,4524 @@ impl Default for PoolOptions {
 struct PoolInner<T: PoolableResource + 'static> {
     options: PoolOptions,
     semaphore: Arc<Semaphore>,
-    idle: Mutex<VecDeque<(T, Instant)>>,
+    idle: Mutex<VecDeque<Idle<T>>>,
     // Make this Arc so CloseEvent can hold a reference and be clone/move-friendly.
     is_closed: Arc<AtomicBool>,
     shutdown: Arc<tokio::sync::Notify>,
     maintenance_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
     hooks: Option<PoolHooks<T>>,
+    factory: Option<ResourceFactory<T>>,
+    events: broadcast::Sender<PoolEvent>,
+    /// Connections currently checked out (including those mid-return).
+    in_flight: AtomicUsize,
+    /// Notified when `in_flight` drops to zero.
+    returned: tokio::sync::Notify,
+    counters: PoolCounters,
+    waiters: std::sync::Mutex<WaitQueue>,
+    /// Checked-out connections, tracked only when `leak_timeout` is set.
+    checkouts: std::sync::Mutex<Checkouts>,
+    metrics: Option<Arc<dyn PoolMetricsRecorder>>,
+    /// Returns that need async work, drained by the maintenance task.
+    returns: mpsc::UnboundedSender<Returned<T>>,
+    breaker: Option<CircuitBreaker>,
+    /// Notified whenever a resource goes back to idle; set by [`KeyedPool`]
+    /// so acquires waiting for budget can evict it.
+    on_idle: Option<Arc<tokio::sync::Notify>>,
 }
 
-/// Generic resource pool.
-pub struct Pool<T: PoolableResource + 'static> {
-    inner: Arc<PoolInner<T>>,
+struct CircuitBreaker {
+    options: CircuitBreakerOptions,
+    state: std::sync::Mutex<BreakerState>,
 }
 
-impl<T: PoolableResource + 'static> Clone for Pool<T> {
-    fn clone(&self) -> Self {
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { admitted: u32, succeeded: u32 },
+}
+
+impl CircuitBreaker {
+    fn new(options: CircuitBreakerOptions) -> Self {
         Self {
-            inner: self.inner.clone(),
+            options,
+            state: std::sync::Mutex::new(BreakerState::Closed { failures: 0 }),
         }
     }
-}
-
-impl<T: PoolableResource + 'static> Pool<T> {
-    /// Create a new pool with the given options.
-    pub fn new(options: PoolOptions) -> Self {
-        let shutdown = Arc::new(tokio::sync::Notify::new());
-        let inner = Arc::new(PoolInner {
-            semaphore: Arc::new(Semaphore::new(options.max_connections)),
-            idle: Mutex::new(VecDeque::new()),
-            is_closed: Arc::new(AtomicBool::new(false)),
-            options: options.clone(),
-            shutdown: shutdown.clone(),
-            maintenance_handle: Mutex::new(None),
-            hooks: None,
-        });
 
-        // Spawn maintenance with Weak so it doesn't keep the pool alive.
-        let weak = Arc::downgrade(&inner);
-        let handle = tokio::spawn(async move {
-            if let Some(inner) = weak.upgrade() {
-                let mut interval = tokio::time::interval(inner.options.health_check_interval);
-                // absorb immediate tick
-                interval.tick().await;
-                loop {
-                    tokio::select! {
-                        _ = inner.shutdown.notified() => {
-                            trace!("pool maintenance: shutdown");
-                            break;
-                        }
-                        _ = interval.tick() => {
-                            trace!("pool maintenance: tick");
-                            Self::cleanup_idle_with(&inner).await;
-                        }
-                    }
-                }
-            }
-        });
-
-        // Store maintenance handle - try_lock should succeed here; fallback to async if not.
-        match inner.maintenance_handle.try_lock() {
-            Ok(mut guard) => {
-                *guard = Some(handle);
-            }
-            Err(_) => {
-                let inner_c = inner.clone();
-                tokio::spawn(async move {
-                    let mut guard = inner_c.maintenance_handle.lock().await;
-                    *guard = Some(handle);
-                });
-            }
+    fn state(&self) -> CircuitState {
+        match *self.state.lock().unwrap() {
+            BreakerState::Closed { .. } => CircuitState::Closed,
+            BreakerState::Open { .. } => CircuitState::Open,
+            BreakerState::HalfOpen { .. } => CircuitState::HalfOpen,
         }
-
-        Self { inner }
     }
 
-    /// Create a new pool with hooks configured.
-    pub fn new_with_hooks(options: PoolOptions, hooks: PoolHooks<T>) -> Self {
-        let shutdown = Arc::new(tokio::sync::Notify::new());
-        let inner = Arc::new(PoolInner {
-            semaphore: Arc::new(Semaphore::new(options.max_connections)),
-            idle: Mutex::new(VecDeque::new()),
-            is_closed: Arc::new(AtomicBool::new(false)),
-            options: options.clone(),
-            shutdown: shutdown.clone(),
-            maintenance_handle: Mutex::new(None),
-            hooks: Some(hooks),
-        });
-
-        // Spawn maintenance with Weak so it doesn't keep the pool alive.
-        let weak = Arc::downgrade(&inner);
-        let handle = tokio::spawn(async move {
-            if let Some(inner) = weak.upgrade() {
-                let mut interval = tokio::time::interval(inner.options.health_check_interval);
-                // absorb immediate tick
-                interval.tick().await;
-                loop {
-                    tokio::select! {
-                        _ = inner.shutdown.notified() => {
-                            trace!("pool maintenance: shutdown");
-                            break;
-                        }
-                        _ = interval.tick() => {
-                            trace!("pool maintenance: tick");
-                            Self::cleanup_idle_with(&inner).await;
-                        }
-                    }
-                }
-            }
-        });
-
-        // Store maintenance handle - try_lock should succeed here; fallback to async if not.
-        match inner.maintenance_handle.try_lock() {
-            Ok(mut guard) => {
-                *guard = Some(handle);
+    /// Let an acquire through, or `None` to fail fast. `Some(true)` marks a probe.
+    /// Also returns the new state if admitting moved the breaker to half-open.
+    fn admit(&self) -> (Option<bool>, Option<CircuitState>) {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            BreakerState::Closed { .. } => (Some(false), None),
+            BreakerState::Open { until } if Instant::now() < *until => (None, None),
+            BreakerState::Open { .. } => {
+                *state = BreakerState::HalfOpen {
+                    admitted: 1,
+                    succeeded: 0,
+                };
+                (Some(true), Some(CircuitState::HalfOpen))
             }
-            Err(_) => {
-                let inner_c = inner.clone();
-                tokio::spawn(async move {
-                    let mut guard = inner_c.maintenance_handle.lock().await;
-                    *guard = Some(handle);
-                });
+            BreakerState::HalfOpen { admitted, .. }
+                if *admitted < self.options.half_open_probes =>
+            {
+                *admitted += 1;
+                (Some(true), None)
             }
+            BreakerState::HalfOpen { .. } => (None, None),
         }
-
-        Self { inner }
     }
 
-    /// Acquire a resource from the pool, creating via factory when needed.
-    pub async fn acquire<F, Fut>(&self, factory: F) -> Result<PoolConnection<T>>
-    where
-        F: FnOnce() -> Fut + Send,
-        Fut: std::future::Future<Output = Result<T>> + Send,
-    {
-        if self.inner.is_closed.load(Ordering::Acquire) {
-            return Err(ShadowcatError::Protocol("Pool closed".into()));
-        }
-
-        // Wait for either: a semaphore permit, or the pool closing.
-        let permit = tokio::time::timeout(self.inner.options.acquire_timeout, async {
-            tokio::select! {
-                _ = self.inner.shutdown.notified() => {
-                    Err::<tokio::sync::OwnedSemaphorePermit, ShadowcatError>(ShadowcatError::Protocol("Pool closed".into()))
-                }
-                res = self.inner.semaphore.clone().acquire_owned() => {
-                    res.map_err(|_| ShadowcatError::PoolExhausted)
-                }
+    fn success(&self, probe: bool) -> Option<CircuitState> {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            BreakerState::Closed { failures } => {
+                *failures = 0;
+                None
             }
-        })
-        .await
-        .map_err(|_| ShadowcatError::Timeout("Pool acquire timeout".into()))??;
-
-        // Try idle repeatedly until we find one acceptable to hooks or none left.
-        while let Some((mut res, since)) = Self::pop_idle_healthy(&self.inner).await {
-            // Run before_acquire if configured
-            if let Some(hooks) = &self.inner.hooks {
-                if let Some(cb) = &hooks.before_acquire {
-                    let meta = PoolConnectionMetadata {
-                        age: Duration::from_secs(0),
-                        idle_for: since.elapsed(),
-                    };
-                    match cb(&mut res, meta).await {
-                        Ok(true) => {
-                            debug!("reusing resource: {}", res.resource_id());
-                            return Ok(PoolConnection {
-                                resource: Some(res),
-                                pool: self.clone(),
-                                permit: Some(permit),
-                            });
-                        }
-                        Ok(false) | Err(_) => {
-                            let _ = res.close().await;
-                            continue;
-                        }
-                    }
+            BreakerState::HalfOpen { succeeded, .. } if probe => {
+                *succeeded += 1;
+                if *succeeded < self.options.half_open_probes {
+                    return None;
                 }
+                *state = BreakerState::Closed { failures: 0 };
+                Some(CircuitState::Closed)
             }
-            // No hook set; reuse directly
-            debug!("reusing resource: {}", res.resource_id());
-            return Ok(PoolConnection {
-                resource: Some(res),
-                pool: self.clone(),
-                permit: Some(permit),
-            });
+            _ => None,
         }
+    }
 
-        // Create new
-        let mut res = factory().await?;
-        if let Some(hooks) = &self.inner.hooks {
-            if let Some(cb) = &hooks.after_create {
-                let meta = PoolConnectionMetadata {
-                    age: Duration::from_secs(0),
-                    idle_for: Duration::from_secs(0),
-                };
-                if let Err(e) = cb(&mut res, meta).await {
-                    let _ = res.close().await;
-                    return Err(e);
+    fn failure(&self) -> Option<CircuitState> {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            BreakerState::Closed { failures } => {
+                *failures += 1;
+                if *failures < self.options.failure_threshold {
+                    return None;
                 }
             }
+            BreakerState::HalfOpen { .. } => {}
+            BreakerState::Open { .. } => return None,
         }
-        Ok(PoolConnection {
-            resource: Some(res),
-            pool: self.clone(),
-            permit: Some(permit),
-        })
+        *state = BreakerState::Open {
+            until: Instant::now() + self.options.cool_down,
+        };
+        Some(CircuitState::Open)
     }
 
-    /// Gracefully close the pool and its idle resources.
-    pub async fn close(&self) {
-        self.inner.is_closed.store(true, Ordering::Release);
-        // Wake all waiters so pending acquires can cancel promptly.
-        self.inner.shutdown.notify_waiters();
-        // Wait for maintenance to finish
-        if let Some(handle) = self.inner.maintenance_handle.lock().await.take() {
-            let _ = handle.await;
-        }
-        // Close all idle
-        let mut idle = self.inner.idle.lock().await;
-        while let Some((mut r, _)) = idle.pop_front() {
-            let _ = r.close().await;
+    /// A probe ended without an outcome (timeout, cancellation); free its slot.
+    fn abandon_probe(&self) {
+        if let BreakerState::HalfOpen { admitted, .. } = &mut *self.state.lock().unwrap() {
+            *admitted = admitted.saturating_sub(1);
         }
     }
+}
 
-    /// Basic stats
-    pub async fn stats(&self) -> PoolStats {
-        let idle = self.inner.idle.lock().await;
-        PoolStats {
-            idle: idle.len() as u64,
-            max: self.inner.options.max_connections as u64,
-            closed: self.inner.is_closed.load(Ordering::Acquire),
+/// A creation let through by the circuit breaker. Dropping it without calling
+/// `succeeded` frees its probe slot, if it was a probe.
+struct Admission<'a, T: PoolableResource + 'static> {
+    inner: &'a PoolInner<T>,
+    probe: bool,
+    settled: bool,
+}
+
+impl<T: PoolableResource + 'static> Admission<'_, T> {
+    fn succeeded(mut self) {
+        self.settled = true;
+        if let Some(breaker) = &self.inner.breaker {
+            self.inner.circuit_changed(breaker.success(self.probe));
         }
     }
+}
 
-    /// Returns true if the pool has been closed.
-    pub fn is_closed(&self) -> bool {
-        self.inner.is_closed.load(Ordering::Acquire)
+impl<T: PoolableResource + 'static> Drop for Admission<'_, T> {
+    fn drop(&mut self) {
+        if self.probe && !self.settled {
+            if let Some(breaker) = &self.inner.breaker {
+                breaker.abandon_probe();
+            }
+        }
     }
+}
 
-    /// Returns a helper that completes when `close()` begins.
-    pub fn close_event(&self) -> CloseEvent {
-        CloseEvent {
-            notify: self.inner.shutdown.clone(),
-            is_closed: self.inner.is_closed.clone(),
+/// Work handed from sync contexts to the maintenance task.
+enum Returned<T> {
+    /// A dropped connection waiting to be released.
+    Release(Live<T>, tokio::sync::OwnedSemaphorePermit),
+    /// A resource abandoned by a cancelled acquire, to be closed.
+    Close(Live<T>),
+}
+
+/// A resource owned by an acquire while it awaits health checks or hooks.
+///
+/// If the acquire is cancelled before `into_live`, the resource goes back to
+/// idle, keeping its original idle time, when it came from there and the idle
+/// queue is free; otherwise maintenance closes it.
+struct Claimed<'a, T: PoolableResource + 'static> {
+    inner: &'a PoolInner<T>,
+    live: Option<Live<T>>,
+    /// When the resource went idle, if it was claimed from the idle queue.
+    idle_since: Option<Instant>,
+}
+
+impl<'a, T: PoolableResource + 'static> Claimed<'a, T> {
+    fn new(inner: &'a PoolInner<T>, live: Live<T>, idle_since: Option<Instant>) -> Self {
+        Self {
+            inner,
+            live: Some(live),
+            idle_since,
         }
     }
 
-    async fn pop_idle_healthy(inner: &Arc<PoolInner<T>>) -> Option<(T, Instant)> {
-        loop {
-            let maybe = {
-                let mut idle = inner.idle.lock().await;
-                idle.pop_front()
-            };
-            let (mut res, since) = maybe?;
+    fn resource(&mut self) -> &mut T {
+        &mut self.live.as_mut().expect("claimed resource").resource
+    }
 
-            if let Some(max_life) = inner.options.max_lifetime {
-                if since.elapsed() > max_life {
-                    let _ = res.close().await;
-                    continue;
-                }
-            }
-            if let Some(idle_to) = inner.options.idle_timeout {
-                if since.elapsed() > idle_to {
-                    let _ = res.close().await;
-                    continue;
-                }
-            }
-            if res.is_healthy().await {
-                return Some((res, since));
-            } else {
-                let _ = res.close().await;
-            }
-        }
+    fn into_live(mut self) -> Live<T> {
+        self.live.take().expect("claimed resource")
     }
+}
 
-    async fn cleanup_idle_with(inner: &Arc<PoolInner<T>>) {
-        let drained: Vec<_> = {
-            let mut idle = inner.idle.lock().await;
-            idle.drain(..).collect()
+impl<T: PoolableResource + 'static> Drop for Claimed<'_, T> {
+    fn drop(&mut self) {
+        let Some(live) = self.live.take() else {
+            return;
         };
-        let mut keep = Vec::new();
-        for (mut r, since) in drained {
-            let mut expired = false;
-            if let Some(max_life) = inner.options.max_lifetime {
-                if since.elapsed() > max_life {
-                    expired = true;
-                }
-            }
-            if let Some(idle_to) = inner.options.idle_timeout {
-                if since.elapsed() > idle_to {
-                    expired = true;
-                }
-            }
-            if expired || !r.is_healthy().await {
-                if let Err(e) = r.close().await {
-                    warn!("error closing idle resource: {}", e);
+        let inner = self.inner;
+        if let Some(idle_since) = self.idle_since {
+            if !inner.is_closed.load(Ordering::Acquire) {
+                if let Ok(mut idle) = inner.idle.try_lock() {
+                    idle.push_back(Idle { live, idle_since });
+                    inner.idle_added();
+                    return;
                 }
-            } else {
-                keep.push((r, since));
             }
         }
-        if !keep.is_empty() {
-            let mut idle = inner.idle.lock().await;
-            idle.extend(keep);
-        }
+        inner.close_later(live);
     }
 }
 
-impl<T: PoolableResource + 'static> Drop for Pool<T> {
-    fn drop(&mut self) {
-        // Best-effort: on last reference, signal shutdown and spawn async idle cleanup.
-        if Arc::strong_count(&self.inner) == 1 {
-            let inner = self.inner.clone();
-            tokio::spawn(async move {
-                inner.is_closed.store(true, Ordering::Release);
-                // Wake all waiters
-                inner.shutdown.notify_waiters();
-                if let Some(handle) = inner.maintenance_handle.lock().await.take() {
-                    let _ = tokio::time::timeout(Duration::from_secs(5), handle).await;
-                }
-                let mut idle = inner.idle.lock().await;
-                let all: Vec<_> = idle.drain(..).collect();
-                drop(idle);
-                for (mut r, _) in all {
-                    let _ = r.close().await;
-                }
-            });
+#[derive(Default)]
+struct Checkouts {
+    next_id: u64,
+    open: HashMap<u64, Checkout>,
+}
+
+struct Checkout {
+    resource_id: String,
+    since: Instant,
+    backtrace: std::backtrace::Backtrace,
+    reported: bool,
+}
+
+/// Cumulative counters surfaced through [`PoolStats`].
+#[derive(Default)]
+struct PoolCounters {
+    created: AtomicU64,
+    closed_idle_timeout: AtomicU64,
+    closed_max_lifetime: AtomicU64,
+    closed_unhealthy: AtomicU64,
+    closed_hook_rejected: AtomicU64,
+    acquire_waits: AtomicU64,
+    acquire_wait_nanos: AtomicU64,
+    leaked: AtomicU64,
+}
+
+impl PoolCounters {
+    fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn expired(&self, reason: ExpiryReason) {
+        match reason {
+            ExpiryReason::IdleTimeout => Self::incr(&self.closed_idle_timeout),
+            ExpiryReason::MaxLifetime => Self::incr(&self.closed_max_lifetime),
         }
     }
 }
 
-/// A close event that fires when `Pool::close()` begins.
-pub struct CloseEvent {
-    notify: Arc<tokio::sync::Notify>,
-    is_closed: Arc<AtomicBool>,
+/// Acquires waiting for capacity. Only the head waits on the semaphore, and
+/// only the waiters whose head status changes are woken.
+#[derive(Default)]
+struct WaitQueue {
+    next_seq: u64,
+    /// Waiters by priority class (indexed by `Priority as usize`), keyed by
+    /// arrival so the first entry is the oldest.
+    classes: [BTreeMap<u64, Waiter>; 3],
+    /// The head waiters were last told about.
+    head: Option<u64>,
 }
 
-impl CloseEvent {
-    /// Returns a future that completes when `Pool::close()` begins.
-    ///
-    /// If the pool is already closed, the returned future completes immediately.
-    /// Otherwise, it awaits an *owned* notification created before waiting,
-    /// avoiding any lifetime issues or double-poll pitfalls.
-    pub fn notified(&self) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
-        if self.is_closed.load(Ordering::Acquire) {
-            Box::pin(async {})
+struct Waiter {
+    priority: Priority,
+    since: Instant,
+    wake: Arc<tokio::sync::Notify>,
+}
+
+impl Waiter {
+    fn effective_priority(&self, starvation_timeout: Duration) -> Priority {
+        if self.since.elapsed() >= starvation_timeout {
+            Priority::High
         } else {
-            Box::pin(self.notify.clone().notified_owned())
+            self.priority
         }
     }
+}
 
-    /// Convenience async wrapper for `.notified()`.
-    pub async fn wait(&self) {
-        self.notified().await;
+impl WaitQueue {
+    fn is_empty(&self) -> bool {
+        self.classes.iter().all(BTreeMap::is_empty)
     }
-}
 
-/// Optional hooks to customize pool behavior, modeled after SQLx semantics.
-type HookUnit<T> = Arc<
-    dyn for<'a> Fn(
-            &'a mut T,
-            PoolConnectionMetadata,
-        ) -> Pin<Box<dyn Future<Output = crate::error::Result<()>> + Send + 'a>>
-        + Send
-        + Sync,
->;
-type HookBool<T> = Arc<
-    dyn for<'a> Fn(
-            &'a mut T,
-            PoolConnectionMetadata,
-        ) -> Pin<Box<dyn Future<Output = crate::error::Result<bool>> + Send + 'a>>
-        + Send
-        + Sync,
->;
+    fn push(&mut self, priority: Priority, wake: Arc<tokio::sync::Notify>) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.classes[priority as usize].insert(
+            seq,
+            Waiter {
+                priority,
+                since: Instant::now(),
+                wake,
+            },
+        );
+        seq
+    }
 
-pub struct PoolHooks<T: PoolableResource + 'static> {
-    /// Called after creating a new resource (not for idle reuse). Return Err to reject and fail acquire.
-    pub after_create: Option<HookUnit<T>>,
-    /// Called before giving out an idle resource. Return Ok(false) or Err to reject; pool closes and tries next.
-    pub before_acquire: Option<HookBool<T>>,
-    /// Called before returning a resource to idle on drop. Return Ok(false) or Err to close instead of requeue.
-    pub after_release: Option<HookBool<T>>,
-}
+    fn remove(&mut self, seq: u64, priority: Priority) {
+        self.classes[priority as usize].remove(&seq);
+    }
 
-/// Metadata passed to hooks.
-#[derive(Clone, Copy, Debug)]
-pub struct PoolConnectionMetadata {
-    pub age: Duration,
-    pub idle_for: Duration,
+    /// The waiter that gets the next permit. Within a class the oldest waiter
+    /// is promoted first, so only each class's oldest can be the head.
+    fn head(&self, starvation_timeout: Duration) -> Option<(u64, &Waiter)> {
+        self.classes
+            .iter()
+            .filter_map(|class| class.first_key_value())
+            .max_by_key(|(seq, w)| {
+                (
+                    w.effective_priority(starvation_timeout),
+                    std::cmp::Reverse(**seq),
+                )
+            })
+            .map(|(seq, w)| (*seq, w))
+    }
+
+    /// Recompute the head; if it moved, wake the old head so it stops waiting
+    /// on the semaphore and the new one so it starts.
+    fn refresh(&mut self, starvation_timeout: Duration) {
+        let new = self.head(starvation_timeout).map(|(seq, _)| seq);
+        if new == self.head {
+            return;
+        }
+        for seq in self.head.into_iter().chain(new) {
+            if let Some(w) = self.classes.iter().find_map(|class| class.get(&seq)) {
+                w.wake.notify_one();
+            }
+        }
+        self.head = new;
+    }
+
+    /// When waiter `seq` will be promoted by the starvation guard, if it has not been yet.
+    fn promotes_at(
+        &self,
+        seq: u64,
+        priority: Priority,
+        starvation_timeout: Duration,
+    ) -> Option<Instant> {
+        self.classes[priority as usize]
+            .get(&seq)
+            .filter(|w| w.priority != Priority::High)
+            .map(|w| w.since + starvation_timeout)
+            .filter(|at| *at > Instant::now())
+    }
 }
 
-/// Handle to a resource checked out from the pool.
-pub struct PoolConnection<T: PoolableResource + 'static> {
-    resource: Option<T>,
-    pool: Pool<T>,
-    permit: Option<tokio::sync::OwnedSemaphorePermit>,
+/// A place in the wait queue; leaving it (on success, timeout or cancellation)
+/// wakes the next head.
+struct WaitTicket<'a, T: PoolableResource + 'static> {
+    inner: &'a PoolInner<T>,
+    seq: u64,
+    priority: Priority,
+    wake: Arc<tokio::sync::Notify>,
 }
 
-impl<T: PoolableResource + 'static> PoolConnection<T> {
-    /// Access the underlying resource mutably.
-    pub fn resource(&mut self) -> &mut T {
-        self.resource.as_mut().expect("resource present")
+impl<'a, T: PoolableResource + 'static> WaitTicket<'a, T> {
+    fn new(inner: &'a PoolInner<T>, priority: Priority) -> Self {
+        let wake = Arc::new(tokio::sync::Notify::new());
+        let mut queue = inner.waiters.lock().unwrap();
+        let seq = queue.push(priority, wake.clone());
+        queue.refresh(inner.options.starvation_timeout);
+        drop(queue);
+        Self {
+            inner,
+            seq,
+            priority,
+            wake,
+        }
     }
 }
 
-impl<T: PoolableResource + 'static> Drop for PoolConnection<T> {
+impl<T: PoolableResource + 'static> Drop for WaitTicket<'_, T> {
     fn drop(&mut self) {
-        if let (Some(mut res), Some(permit)) = (self.resource.take(), self.permit.take()) {
-            let pool = self.pool.clone();
-            // Return resource to idle in a task and release capacity AFTER requeue by consuming permit at end of task.
-            tokio::spawn(async move {
-                if pool.inner.is_closed.load(Ordering::Acquire) || !res.is_healthy().await {
-                    let _ = res.close().await;
-                    drop(permit);
-                    return;
-                }
-                // Apply after_release hook if configured
-                if let Some(hooks) = &pool.inner.hooks {
-                    if let Some(cb) = &hooks.after_release {
-                        let meta = PoolConnectionMetadata {
-                            age: Duration::from_secs(0),
-                            idle_for: Duration::from_secs(0),
-                        };
-                        match cb(&mut res, meta).await {
-                            Ok(true) => {
-                                let mut idle = pool.inner.idle.lock().await;
-                                idle.push_back((res, Instant::now()));
-                                drop(permit);
-                                debug!("resource returned to pool idle");
-                                return;
-                            }
-                            Ok(false) | Err(_) => {
-                                let _ = res.close().await;
-                                drop(permit);
-                                return;
-                            }
-                        }
-                    }
-                }
-                let mut idle = pool.inner.idle.lock().await;
-                idle.push_back((res, Instant::now()));
-                drop(permit);
-                debug!("resource returned to pool idle");
-            });
-        }
+        let mut queue = self.inner.waiters.lock().unwrap();
+        queue.remove(self.seq, self.priority);
+        queue.refresh(self.inner.options.starvation_timeout);
     }
 }
 
-/// Pool statistics snapshot.
-#[derive(Debug, Clone)]
-pub struct PoolStats {
-    pub idle: u64,
-    pub max: u64,
-    pub closed: bool,
-}
+/// Buffered lifecycle events per pool; slow subscribers see `Lagged`.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use async_trait::async_trait;
-    use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
+impl<T: PoolableResource + 'static> PoolInner<T> {
+    /// Record a lifecycle event with the metrics recorder, and publish it if
+    /// anyone is subscribed.
+    fn emit(&self, kind: PoolEventKind, resource: Option<&T>) {
+        if let Some(metrics) = &self.metrics {
+            match kind {
+                PoolEventKind::Created => metrics.created(),
+                PoolEventKind::HealthCheckFailed => metrics.health_check_failed(),
+                PoolEventKind::Expired(reason) => metrics.expired(reason),
+                PoolEventKind::Released => metrics.released(),
+                PoolEventKind::AcquireTimeout => metrics.acquire_timed_out(),
+                _ => {}
+            }
+        }
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.events.send(PoolEvent {
+            kind,
+            resource_id: resource.map(|r| r.resource_id()),
+            at: SystemTime::now(),
+        });
+    }
 
-    struct TestResource {
-        id: String,
-        healthy: Arc<AtomicBool>,
-        closed: Arc<AtomicBool>,
+    /// Ask the circuit breaker to let a creation through.
+    fn admit(&self) -> Result<Admission<'_, T>> {
+        let probe = match &self.breaker {
+            None => false,
+            Some(breaker) => {
+                let (admitted, changed) = breaker.admit();
+                self.circuit_changed(changed);
+                admitted.ok_or_else(|| ShadowcatError::Protocol("Circuit breaker open".into()))?
+            }
+        };
+        Ok(Admission {
+            inner: self,
+            probe,
+            settled: false,
+        })
     }
 
-    #[async_trait]
-    impl PoolableResource for TestResource {
-        async fn is_healthy(&self) -> bool {
-            self.healthy.load(std::sync::atomic::Ordering::Relaxed)
+    fn record_create_failed(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.create_failed();
         }
+        self.record_failure();
+    }
 
-        async fn close(&mut self) -> Result<()> {
-            self.closed
-                .store(true, std::sync::atomic::Ordering::Relaxed);
-            Ok(())
+    /// Log a hook error and pass it to `on_hook_error`.
+    fn hook_failed(&self, hook: PoolHook, resource: &T, error: &ShadowcatError) {
+        let resource_id = resource.resource_id();
+        warn!("pool {:?} hook failed for {}: {}", hook, resource_id, error);
+        if let Some(cb) = self.hooks.as_ref().and_then(|h| h.on_hook_error.as_ref()) {
+            cb(&HookError {
+                hook,
+                resource_id,
+                error,
+            });
         }
+    }
 
-        fn resource_id(&self) -> String {
-            self.id.clone()
+    /// Count an upstream failure against the circuit breaker.
+    fn record_failure(&self) {
+        if let Some(breaker) = &self.breaker {
+            self.circuit_changed(breaker.failure());
         }
     }
 
-    fn make_options() -> PoolOptions {
-        PoolOptions {
-            max_connections: 1,
-            acquire_timeout: Duration::from_millis(200),
-            idle_timeout: Some(Duration::from_millis(200)),
-            max_lifetime: Some(Duration::from_secs(60)),
-            health_check_interval: Duration::from_millis(50),
-        }
+    /// Whether background creation (warm-up, replacement) may call the factory.
+    fn circuit_closed(&self) -> bool {
+        self.breaker
+            .as_ref()
+            .is_none_or(|b| b.state() == CircuitState::Closed)
     }
 
-    #[tokio::test]
-    async fn test_acquire_and_reuse() {
-        let pool = Pool::<TestResource>::new(make_options());
-        let healthy = Arc::new(AtomicBool::new(true));
-        let closed = Arc::new(AtomicBool::new(false));
+    fn record_unhealthy(&self) {
+        PoolCounters::incr(&self.counters.closed_unhealthy);
+        self.record_failure();
+    }
 
-        let mut ids = Vec::new();
+    fn circuit_changed(&self, state: Option<CircuitState>) {
+        if let Some(state) = state {
+            warn!("pool circuit breaker: {:?}", state);
+            self.emit(PoolEventKind::CircuitStateChanged(state), None);
+        }
+    }
 
-        // First acquire creates a resource
-        {
-            let mut c = pool
-                .acquire({
-                    let healthy = healthy.clone();
-                    let closed = closed.clone();
-                    move || {
-                        let healthy = healthy.clone();
-                        let closed = closed.clone();
-                        async move {
-                            Ok(TestResource {
-                                id: "res-1".into(),
-                                healthy,
-                                closed,
-                            })
-                        }
-                    }
-                })
-                .await
-                .expect("acquire should succeed");
-            ids.push(c.resource().resource_id());
-        } // drop returns to idle
+    /// Run `is_healthy()` if the health-check policy asks for it.
+    async fn passes_health_check(&self, resource: &T, meta: PoolConnectionMetadata) -> bool {
+        if !self.options.health_check.should_check(meta).await {
+            return true;
+        }
+        let Some(limit) = self.options.health_check_timeout else {
+            return resource.is_healthy().await;
+        };
+        match tokio::time::timeout(limit, resource.is_healthy()).await {
+            Ok(healthy) => healthy,
+            Err(_) => {
+                warn!(
+                    "health check for {} timed out after {:?}",
+                    resource.resource_id(),
+                    limit
+                );
+                false
+            }
+        }
+    }
 
-        // Second acquire should reuse
+    /// Close `live` from maintenance, or a task of its own if maintenance has stopped.
+    fn close_later(&self, live: Live<T>) {
+        if let Err(mpsc::error::SendError(Returned::Close(mut live))) =
+            self.returns.send(Returned::Close(live))
         {
-            let mut c = pool
-                .acquire(|| async {
-                    Err::<TestResource, ShadowcatError>(ShadowcatError::Protocol(
-                        "should not create".into(),
-                    ))
-                })
-                .await
-                .expect("reuse should succeed");
-            ids.push(c.resource().resource_id());
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move {
+                    let _ = live.resource.close().await;
+                });
+            }
         }
+    }
 
-        assert_eq!(ids[0], ids[1], "resource should be reused");
-        let stats = pool.stats().await;
-        assert!(stats.idle <= 1);
-        assert_eq!(stats.max, 1);
-        assert!(!stats.closed);
+    /// Wrap a freshly created resource, labelling it with the `labels` hook.
+    fn new_live(&self, resource: T) -> Live<T> {
+        let mut live = Live::new(resource);
+        if let Some(label) = self.hooks.as_ref().and_then(|h| h.labels.as_ref()) {
+            live.labels = label(&live.resource);
+        }
+        live
     }
 
-    #[tokio::test]
-    async fn test_close_marks_closed_and_drains_idle() {
-        let pool = Pool::<TestResource>::new(make_options());
-        let healthy = Arc::new(AtomicBool::new(true));
-        let closed_flag = Arc::new(AtomicBool::new(false));
+    /// Wake keyed-pool acquires waiting for budget; call after adding to idle.
+    fn idle_added(&self) {
+        if let Some(on_idle) = &self.on_idle {
+            on_idle.notify_waiters();
+        }
+    }
 
-        // acquire and drop once to populate idle
-        {
-            let _c = pool
-                .acquire({
-                    let healthy = healthy.clone();
-                    let closed_flag = closed_flag.clone();
-                    move || {
-                        let healthy = healthy.clone();
-                        let closed_flag = closed_flag.clone();
-                        async move {
-                            Ok(TestResource {
-                                id: "x".into(),
-                                healthy,
-                                closed: closed_flag,
-                            })
-                        }
-                    }
-                })
-                .await
-                .unwrap();
+    /// Record that a checked-out connection has finished returning.
+    fn checked_in(&self) {
+        if self.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.returned.notify_waiters();
         }
+    }
+}
 
-        pool.close().await;
-        let stats_after = pool.stats().await;
-        assert!(stats_after.closed);
-        assert!(
-            closed_flag.load(Ordering::Relaxed),
-            "resource should be closed during pool.close()"
-        );
+/// A pooled resource with the bookkeeping the pool tracks for its whole life.
+struct Live<T> {
+    resource: T,
+    created_at: Instant,
+    labels: Labels,
+    /// A [`KeyedPool`]'s budget slot, held for as long as the resource is open.
+    budget: Option<tokio::sync::OwnedSemaphorePermit>,
+}
 
-        // Further acquires should fail fast
-        let res = pool.acquire(|| async { unreachable!() }).await;
-        assert!(res.is_err());
+impl<T> Live<T> {
+    fn new(resource: T) -> Self {
+        Self {
+            resource,
+            created_at: Instant::now(),
+            labels: Labels::new(),
+            budget: None,
+        }
     }
 
-    #[tokio::test]
-    async fn test_idle_timeout_cleanup() {
-        let mut options = make_options();
-        options.idle_timeout = Some(Duration::from_millis(50));
-        options.health_check_interval = Duration::from_millis(30);
-        let pool = Pool::<TestResource>::new(options);
-        let healthy = Arc::new(AtomicBool::new(true));
-        let closed_flag = Arc::new(AtomicBool::new(false));
+    fn into_idle(self) -> Idle<T> {
+        Idle {
+            live: self,
+            idle_since: Instant::now(),
+        }
+    }
 
-        {
-            let _c = pool
-                .acquire({
-                    let healthy = healthy.clone();
-                    let closed_flag = closed_flag.clone();
-                    move || {
-                        let healthy = healthy.clone();
-                        let closed_flag = closed_flag.clone();
-                        async move {
-                            Ok(TestResource {
-                                id: "y".into(),
-                                healthy,
-                                closed: closed_flag,
-                            })
-                        }
-                    }
-                })
-                .await
-                .unwrap();
+    fn is_past_lifetime(&self, max_lifetime: Option<Duration>) -> bool {
+        max_lifetime.is_some_and(|max| self.created_at.elapsed() > max)
+    }
+
+    fn metadata(&self) -> PoolConnectionMetadata {
+        PoolConnectionMetadata {
+            age: self.created_at.elapsed(),
+            idle_for: Duration::from_secs(0),
         }
+    }
+}
 
-        // Wait enough for idle timeout + maintenance tick
-        tokio::time::sleep(Duration::from_millis(120)).await;
+/// A live resource waiting in the idle queue.
+struct Idle<T> {
+    live: Live<T>,
+    idle_since: Instant,
+}
 
-        // Acquire again should create a new resource as old idle was cleaned
-        let new = pool
-            .acquire({
+impl<T> Idle<T> {
+    fn is_past_idle_timeout(&self, idle_timeout: Option<Duration>) -> bool {
+        idle_timeout.is_some_and(|to| self.idle_since.elapsed() > to)
+    }
+
+    fn metadata(&self) -> PoolConnectionMetadata {
+        PoolConnectionMetadata {
+            age: self.live.created_at.elapsed(),
+            idle_for: self.idle_since.elapsed(),
+        }
+    }
+}
+
+/// String labels the pool keeps alongside each resource, e.g. the negotiated
+/// protocol version or upstream region.
+pub type Labels = BTreeMap<String, String>;
+
+/// Predicate over [`Labels`] for [`Pool::acquire_matching`].
+type LabelFilter<'a> = Option<&'a (dyn Fn(&Labels) -> bool + Send + Sync)>;
+
+/// Factory the pool uses to create resources without a caller-supplied closure.
+pub type ResourceFactory<T> =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<T>> + Send>> + Send + Sync>;
+
+/// Generic resource pool.
+pub struct Pool<T: PoolableResource + 'static> {
+    inner: Arc<PoolInner<T>>,
+}
+
+impl<T: PoolableResource + 'static> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: PoolableResource + 'static> Pool<T> {
+    /// Start building a pool; see [`PoolBuilder`].
+    pub fn builder() -> PoolBuilder<T> {
+        PoolBuilder::new()
+    }
+
+    /// Create a new pool with the given options.
+    pub fn new(options: PoolOptions) -> Self {
+        Self::from_parts(PoolBuilder {
+            options,
+            ..PoolBuilder::new()
+        })
+    }
+
+    /// Create a new pool with hooks configured.
+    pub fn new_with_hooks(options: PoolOptions, hooks: PoolHooks<T>) -> Self {
+        Self::from_parts(PoolBuilder {
+            options,
+            hooks: Some(hooks),
+            ..PoolBuilder::new()
+        })
+    }
+
+    /// Create a new pool that owns a factory. Maintenance uses it to keep
+    /// `min_connections` resources warm.
+    pub fn new_with_factory(options: PoolOptions, factory: ResourceFactory<T>) -> Self {
+        Self::from_parts(PoolBuilder {
+            options,
+            factory: Some(factory),
+            ..PoolBuilder::new()
+        })
+    }
+
+    fn from_parts(parts: PoolBuilder<T>) -> Self {
+        let PoolBuilder {
+            options,
+            hooks,
+            factory,
+            metrics,
+            on_idle,
+        } = parts;
+        let shutdown = Arc::new(tokio::sync::Notify::new());
+        let (returns, returns_rx) = mpsc::unbounded_channel();
+        let inner = Arc::new(PoolInner {
+            semaphore: Arc::new(Semaphore::new(options.max_connections)),
+            idle: Mutex::new(VecDeque::new()),
+            is_closed: Arc::new(AtomicBool::new(false)),
+            options: options.clone(),
+            shutdown: shutdown.clone(),
+            maintenance_handle: Mutex::new(None),
+            hooks,
+            factory,
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            in_flight: AtomicUsize::new(0),
+            returned: tokio::sync::Notify::new(),
+            counters: PoolCounters::default(),
+            waiters: std::sync::Mutex::new(WaitQueue::default()),
+            checkouts: std::sync::Mutex::new(Checkouts::default()),
+            metrics,
+            returns,
+            breaker: options.circuit_breaker.clone().map(CircuitBreaker::new),
+            on_idle,
+        });
+
+        let handle = Self::spawn_maintenance(&inner, returns_rx);
+
+        // Store maintenance handle - try_lock should succeed here; fallback to async if not.
+        match inner.maintenance_handle.try_lock() {
+            Ok(mut guard) => {
+                *guard = Some(handle);
+            }
+            Err(_) => {
+                let inner_c = inner.clone();
+                tokio::spawn(async move {
+                    let mut guard = inner_c.maintenance_handle.lock().await;
+                    *guard = Some(handle);
+                });
+            }
+        }
+
+        Self { inner }
+    }
+
+    /// Spawn maintenance with Weak so it doesn't keep the pool alive.
+    ///
+    /// Alongside the periodic pass, the task releases connections whose return
+    /// needs async work (health checks, hooks, closing). Up to
+    /// `health_check_concurrency` releases run at once, started in the order
+    /// the connections were dropped, and a slow one never delays the others or
+    /// waits for a maintenance pass.
+    fn spawn_maintenance(
+        inner: &Arc<PoolInner<T>>,
+        returns: mpsc::UnboundedReceiver<Returned<T>>,
+    ) -> tokio::task::JoinHandle<()> {
+        let weak = Arc::downgrade(inner);
+        let shutdown = inner.shutdown.clone();
+        let is_closed = inner.is_closed.clone();
+        let period = inner.options.health_check_interval;
+        let releases = Self::drain_returns(
+            weak.clone(),
+            returns,
+            shutdown.clone(),
+            is_closed.clone(),
+            inner.options.health_check_concurrency.max(1),
+        );
+        let maintenance = async move {
+            let mut interval = tokio::time::interval(period);
+            // absorb immediate tick
+            interval.tick().await;
+            // Warm up right away so the first acquires find idle resources.
+            match weak.upgrade() {
+                Some(inner) => Self::maintain_min_connections(&inner).await,
+                None => return,
+            }
+            loop {
+                // Create the waiter before checking the flag so a close() that
+                // races with task startup is never missed.
+                let notified = shutdown.notified();
+                if is_closed.load(Ordering::Acquire) {
+                    break;
+                }
+                tokio::select! {
+                    _ = notified => {
+                        trace!("pool maintenance: shutdown");
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let Some(inner) = weak.upgrade() else {
+                            break;
+                        };
+                        trace!("pool maintenance: tick");
+                        Self::cleanup_idle_with(&inner).await;
+                        Self::maintain_min_connections(&inner).await;
+                        Self::report_leaks(&inner);
+                        Self::record_gauges(&inner).await;
+                    }
+                }
+            }
+        };
+        tokio::spawn(async move {
+            tokio::join!(maintenance, releases);
+        })
+    }
+
+    /// Release queued returns concurrently until the pool closes or is dropped,
+    /// then release whatever is still queued.
+    async fn drain_returns(
+        weak: std::sync::Weak<PoolInner<T>>,
+        mut returns: mpsc::UnboundedReceiver<Returned<T>>,
+        shutdown: Arc<tokio::sync::Notify>,
+        is_closed: Arc<AtomicBool>,
+        concurrency: usize,
+    ) {
+        let notified = shutdown.notified();
+        if !is_closed.load(Ordering::Acquire) {
+            futures::stream::poll_fn(|cx| returns.poll_recv(cx))
+                .take_until(notified)
+                .for_each_concurrent(concurrency, |returned| {
+                    Self::finish_return(weak.upgrade(), returned)
+                })
+                .await;
+        }
+        // Later drops fall back to spawning their own release.
+        returns.close();
+        while let Ok(returned) = returns.try_recv() {
+            Self::finish_return(weak.upgrade(), returned).await;
+        }
+    }
+
+    /// Run one maintenance pass (idle cleanup, warm-up, leak reporting) now,
+    /// instead of waiting for the next `health_check_interval` tick.
+    #[cfg(any(test, feature = "test-util"))]
+    pub async fn run_maintenance_now(&self) {
+        Self::cleanup_idle_with(&self.inner).await;
+        Self::maintain_min_connections(&self.inner).await;
+        Self::report_leaks(&self.inner);
+        Self::record_gauges(&self.inner).await;
+    }
+
+    /// Publish idle/active gauges to the metrics recorder.
+    async fn record_gauges(inner: &Arc<PoolInner<T>>) {
+        if let Some(metrics) = &inner.metrics {
+            let idle = inner.idle.lock().await.len();
+            metrics.connections(idle, inner.in_flight.load(Ordering::Acquire));
+        }
+    }
+
+    /// Release a queued return, or just close it if the pool is gone.
+    async fn finish_return(inner: Option<Arc<PoolInner<T>>>, returned: Returned<T>) {
+        match (inner, returned) {
+            (Some(inner), Returned::Release(live, permit)) => {
+                Self::release(&inner, live, permit).await;
+                inner.checked_in();
+            }
+            (None, Returned::Release(mut live, ..)) | (_, Returned::Close(mut live)) => {
+                let _ = live.resource.close().await;
+            }
+        }
+    }
+
+    /// Acquire a resource from the pool, creating via the pool's factory when needed.
+    ///
+    /// Fails immediately if the pool was built without a factory; use
+    /// [`Pool::acquire_with`] to supply one per call instead.
+    ///
+    /// Cancel-safe: dropping the future at any await releases its capacity, and
+    /// a resource it was checking goes back to idle (or is closed if new).
+    pub async fn acquire(&self) -> Result<PoolConnection<T>> {
+        self.acquire_with_priority(Priority::Normal).await
+    }
+
+    /// Like [`Pool::acquire`], but waits for capacity in the given priority class.
+    ///
+    /// When the pool is at capacity, higher-priority waiters are served first;
+    /// a waiter passed over for longer than `starvation_timeout` is served as
+    /// [`Priority::High`].
+    pub async fn acquire_with_priority(&self, priority: Priority) -> Result<PoolConnection<T>> {
+        self.acquire_from_factory(priority, None).await
+    }
+
+    /// Like [`Pool::acquire`], but only reuses idle resources whose labels
+    /// satisfy `matches`. Creates a new resource when none do.
+    pub async fn acquire_matching<P>(&self, matches: P) -> Result<PoolConnection<T>>
+    where
+        P: Fn(&Labels) -> bool + Send + Sync,
+    {
+        self.acquire_from_factory(Priority::Normal, Some(&matches))
+            .await
+    }
+
+    async fn acquire_from_factory(
+        &self,
+        priority: Priority,
+        filter: LabelFilter<'_>,
+    ) -> Result<PoolConnection<T>> {
+        let create = self.create_from_factory()?;
+        self.acquire_inner(priority, filter, move || create).await
+    }
+
+    /// A creation with the stored factory, retried per `create_retry` until
+    /// `acquire_timeout` from now.
+    fn create_from_factory(&self) -> Result<impl Future<Output = Result<T>> + Send + 'static> {
+        let factory = self
+            .inner
+            .factory
+            .clone()
+            .ok_or_else(|| ShadowcatError::Protocol("Pool has no factory configured".into()))?;
+        let deadline = Instant::now() + self.inner.options.acquire_timeout;
+        let retry = self.inner.options.create_retry.clone();
+        Ok(Self::create_with_retry(factory, retry, deadline))
+    }
+
+    /// Call `factory`, retrying failures per `retry` while the next attempt can
+    /// start before `deadline`.
+    async fn create_with_retry(
+        factory: ResourceFactory<T>,
+        retry: Option<RetryPolicy>,
+        deadline: Instant,
+    ) -> Result<T> {
+        let Some(retry) = retry else {
+            return factory().await;
+        };
+        let mut attempt = 1;
+        loop {
+            let err = match factory().await {
+                Ok(res) => return Ok(res),
+                Err(e) => e,
+            };
+            if attempt >= retry.max_attempts {
+                return Err(err);
+            }
+            let delay = retry.delay(attempt);
+            if Instant::now() + delay >= deadline {
+                return Err(err);
+            }
+            debug!(
+                "resource creation failed (attempt {}): {}; retrying in {:?}",
+                attempt, err, delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Acquire a resource from the pool, creating via `factory` when needed.
+    pub async fn acquire_with<F, Fut>(&self, factory: F) -> Result<PoolConnection<T>>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: std::future::Future<Output = Result<T>> + Send,
+    {
+        self.acquire_inner(Priority::Normal, None, factory).await
+    }
+
+    async fn acquire_inner<F, Fut>(
+        &self,
+        priority: Priority,
+        filter: LabelFilter<'_>,
+        factory: F,
+    ) -> Result<PoolConnection<T>>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: std::future::Future<Output = Result<T>> + Send,
+    {
+        if self.inner.is_closed.load(Ordering::Acquire) {
+            return Err(ShadowcatError::Protocol("Pool closed".into()));
+        }
+
+        let started = Instant::now();
+        let permit = self.wait_for_permit(priority).await?;
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.acquire_wait(started.elapsed());
+        }
+        self.checkout(permit, filter, factory).await
+    }
+
+    /// Hand out an idle resource under `permit`, or create one via `factory`.
+    ///
+    /// Only creation goes through the circuit breaker; healthy idle resources
+    /// stay usable while it is open.
+    async fn checkout<F, Fut>(
+        &self,
+        permit: tokio::sync::OwnedSemaphorePermit,
+        filter: LabelFilter<'_>,
+        factory: F,
+    ) -> Result<PoolConnection<T>>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: std::future::Future<Output = Result<T>> + Send,
+    {
+        if let Some(live) = self.reuse_idle(filter).await {
+            return Ok(self.connection(live, permit));
+        }
+        let admission = self.inner.admit()?;
+        // Idle resources the filter skipped still count toward max_connections.
+        while filter.is_some()
+            && Self::live_count(&self.inner).await > self.inner.options.max_connections
+            && Self::evict_one_unmatched(&self.inner, filter).await
+        {}
+        let live = self.create(factory).await?;
+        admission.succeeded();
+        Ok(self.connection(live, permit))
+    }
+
+    /// Get a semaphore permit, waiting up to `acquire_timeout` if none is free.
+    async fn wait_for_permit(
+        &self,
+        priority: Priority,
+    ) -> Result<tokio::sync::OwnedSemaphorePermit> {
+        if let Some(permit) = self.try_permit() {
+            return Ok(permit);
+        }
+        PoolCounters::incr(&self.inner.counters.acquire_waits);
+        let started = Instant::now();
+
+        // Wait for either: a semaphore permit, or the pool closing.
+        let permit = tokio::time::timeout(self.inner.options.acquire_timeout, async {
+            tokio::select! {
+                _ = self.inner.shutdown.notified() => {
+                    Err::<tokio::sync::OwnedSemaphorePermit, ShadowcatError>(ShadowcatError::Protocol("Pool closed".into()))
+                }
+                res = self.wait_in_queue(priority) => res,
+            }
+        })
+        .await;
+        self.inner
+            .counters
+            .acquire_wait_nanos
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        permit.map_err(|_| {
+            self.inner.emit(PoolEventKind::AcquireTimeout, None);
+            ShadowcatError::Timeout("Pool acquire timeout".into())
+        })?
+    }
+
+    /// A free permit, unless someone is already queued for one: a free permit
+    /// goes to existing waiters first.
+    fn try_permit(&self) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        if !self.inner.waiters.lock().unwrap().is_empty() {
+            return None;
+        }
+        self.inner.semaphore.clone().try_acquire_owned().ok()
+    }
+
+    /// Wait for a permit behind higher-priority and older waiters.
+    async fn wait_in_queue(&self, priority: Priority) -> Result<tokio::sync::OwnedSemaphorePermit> {
+        let inner = &self.inner;
+        let starvation_timeout = inner.options.starvation_timeout;
+        let ticket = WaitTicket::new(inner, priority);
+        loop {
+            let (is_head, promotes_at) = {
+                let queue = inner.waiters.lock().unwrap();
+                (
+                    queue.head(starvation_timeout).map(|(seq, _)| seq) == Some(ticket.seq),
+                    queue.promotes_at(ticket.seq, priority, starvation_timeout),
+                )
+            };
+            // Wake-ups are stored by `notify_one`, so none is lost between the
+            // check above and the wait below.
+            let changed = ticket.wake.notified();
+            if is_head {
+                tokio::select! {
+                    res = inner.semaphore.clone().acquire_owned() => {
+                        return res.map_err(|_| ShadowcatError::PoolExhausted);
+                    }
+                    _ = changed => {}
+                }
+            } else {
+                let promoted = async {
+                    match promotes_at {
+                        Some(at) => tokio::time::sleep_until(at).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    _ = changed => {}
+                    _ = promoted => {
+                        // We may now outrank the head; hand over if so.
+                        inner.waiters.lock().unwrap().refresh(starvation_timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Take an idle resource only if one is immediately available.
+    ///
+    /// Never waits for capacity and never invokes a factory; returns `None` when
+    /// the pool is closed, at capacity, has acquires waiting, or has no
+    /// acceptable idle resource.
+    pub async fn try_acquire(&self) -> Option<PoolConnection<T>> {
+        if self.inner.is_closed.load(Ordering::Acquire) {
+            return None;
+        }
+        let permit = self.try_permit()?;
+        let live = self.reuse_idle(None).await?;
+        Some(self.connection(live, permit))
+    }
+
+    /// Like [`Pool::try_acquire`], but creates a resource with the pool's factory
+    /// when capacity is instantly available and nothing is idle.
+    ///
+    /// Returns `Ok(None)` when the pool is at capacity or has acquires waiting.
+    pub async fn try_acquire_or_create(&self) -> Result<Option<PoolConnection<T>>> {
+        if self.inner.is_closed.load(Ordering::Acquire) {
+            return Err(ShadowcatError::Protocol("Pool closed".into()));
+        }
+        let factory = self
+            .inner
+            .factory
+            .clone()
+            .ok_or_else(|| ShadowcatError::Protocol("Pool has no factory configured".into()))?;
+        let Some(permit) = self.try_permit() else {
+            return Ok(None);
+        };
+        self.checkout(permit, None, move || factory())
+            .await
+            .map(Some)
+    }
+
+    /// Pop idle resources until one passes health checks and `before_acquire`.
+    async fn reuse_idle(&self, filter: LabelFilter<'_>) -> Option<Live<T>> {
+        // Try idle repeatedly until we find one acceptable to hooks or none left.
+        while let Some((mut claimed, meta)) = Self::pop_idle_healthy(&self.inner, filter).await {
+            // Run before_acquire if configured
+            if let Some(hooks) = &self.inner.hooks {
+                if let Some(cb) = &hooks.before_acquire {
+                    match cb(claimed.resource(), meta).await {
+                        Ok(true) => {}
+                        rejected => {
+                            let mut live = claimed.into_live();
+                            if let Err(e) = rejected {
+                                self.inner
+                                    .hook_failed(PoolHook::BeforeAcquire, &live.resource, &e);
+                            }
+                            PoolCounters::incr(&self.inner.counters.closed_hook_rejected);
+                            let _ = live.resource.close().await;
+                            continue;
+                        }
+                    }
+                }
+            }
+            let live = claimed.into_live();
+            debug!("reusing resource: {}", live.resource.resource_id());
+            self.inner.emit(PoolEventKind::Reused, Some(&live.resource));
+            return Some(live);
+        }
+        None
+    }
+
+    /// Create a resource with `factory` and run `after_create`.
+    async fn create<F, Fut>(&self, factory: F) -> Result<Live<T>>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: std::future::Future<Output = Result<T>> + Send,
+    {
+        let res = factory()
+            .await
+            .inspect_err(|_| self.inner.record_create_failed())?;
+        let mut claimed = Claimed::new(&self.inner, Live::new(res), None);
+        if let Err(e) = Self::run_after_create(&self.inner, claimed.resource()).await {
+            // Already closed by run_after_create.
+            drop(claimed.into_live());
+            return Err(e);
+        }
+        let live = self.inner.new_live(claimed.into_live().resource);
+        PoolCounters::incr(&self.inner.counters.created);
+        self.inner
+            .emit(PoolEventKind::Created, Some(&live.resource));
+        Ok(live)
+    }
+
+    fn connection(
+        &self,
+        live: Live<T>,
+        permit: tokio::sync::OwnedSemaphorePermit,
+    ) -> PoolConnection<T> {
+        self.inner.in_flight.fetch_add(1, Ordering::AcqRel);
+        if let Some(metrics) = &self.inner.metrics {
+            metrics.acquired();
+        }
+        let checkout = self.inner.options.leak_timeout.map(|_| {
+            let mut checkouts = self.inner.checkouts.lock().unwrap();
+            let id = checkouts.next_id;
+            checkouts.next_id += 1;
+            checkouts.open.insert(
+                id,
+                Checkout {
+                    resource_id: live.resource.resource_id(),
+                    since: Instant::now(),
+                    backtrace: std::backtrace::Backtrace::capture(),
+                    reported: false,
+                },
+            );
+            id
+        });
+        PoolConnection {
+            resource: Some(live),
+            pool: self.clone(),
+            permit: Some(permit),
+            checkout,
+        }
+    }
+
+    /// Warn once about each connection held longer than `leak_timeout`.
+    fn report_leaks(inner: &Arc<PoolInner<T>>) {
+        let Some(leak_timeout) = inner.options.leak_timeout else {
+            return;
+        };
+        let mut checkouts = inner.checkouts.lock().unwrap();
+        for checkout in checkouts.open.values_mut() {
+            if checkout.reported || checkout.since.elapsed() < leak_timeout {
+                continue;
+            }
+            checkout.reported = true;
+            PoolCounters::incr(&inner.counters.leaked);
+            warn!(
+                "possible connection leak: {} held for {:?}; acquired at:\n{}",
+                checkout.resource_id,
+                checkout.since.elapsed(),
+                checkout.backtrace
+            );
+        }
+    }
+
+    /// Gracefully close the pool and its idle resources.
+    pub async fn close(&self) {
+        self.inner.is_closed.store(true, Ordering::Release);
+        // Wake all waiters so pending acquires can cancel promptly.
+        self.inner.shutdown.notify_waiters();
+        // Wait for maintenance to finish
+        if let Some(handle) = self.inner.maintenance_handle.lock().await.take() {
+            let _ = handle.await;
+        }
+        // Close all idle
+        let mut idle = self.inner.idle.lock().await;
+        while let Some(mut r) = idle.pop_front() {
+            let _ = r.live.resource.close().await;
+        }
+        self.inner.emit(PoolEventKind::Closed, None);
+    }
+
+    /// Close the pool, waiting up to `deadline` for checked-out connections.
+    ///
+    /// New acquires fail immediately. Connections returned before the deadline
+    /// are closed as they come back; once all are back (or the deadline passes)
+    /// idle resources are closed as in [`Pool::close`].
+    ///
+    /// Best-effort: the pool cannot take a resource back from its holder, so
+    /// connections still checked out at the deadline are not force-closed.
+    /// Their count is returned, and each is closed when its holder drops it.
+    /// Holders that must let go promptly should watch [`Pool::close_event`].
+    pub async fn close_gracefully(&self, deadline: Duration) -> usize {
+        self.inner.is_closed.store(true, Ordering::Release);
+        self.inner.shutdown.notify_waiters();
+        let drained = tokio::time::timeout(deadline, async {
+            loop {
+                let returned = self.inner.returned.notified();
+                if self.inner.in_flight.load(Ordering::Acquire) == 0 {
+                    break;
+                }
+                returned.await;
+            }
+        })
+        .await
+        .is_ok();
+        self.close().await;
+        let stragglers = self.inner.in_flight.load(Ordering::Acquire);
+        if !drained {
+            warn!(
+                "pool closed with {} connection(s) still checked out after {:?}",
+                stragglers, deadline
+            );
+        }
+        stragglers
+    }
+
+    /// Subscribe to pool lifecycle events.
+    ///
+    /// Events are only buffered while at least one receiver exists; a receiver
+    /// that falls more than the channel capacity behind observes `Lagged`.
+    pub fn events(&self) -> broadcast::Receiver<PoolEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// Basic stats
+    pub async fn stats(&self) -> PoolStats {
+        let idle = self.inner.idle.lock().await;
+        let counters = &self.inner.counters;
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        PoolStats {
+            idle: idle.len() as u64,
+            max: self.inner.options.max_connections as u64,
+            closed: self.inner.is_closed.load(Ordering::Acquire),
+            active: self.inner.in_flight.load(Ordering::Acquire) as u64,
+            total_created: load(&counters.created),
+            total_closed: ClosedStats {
+                idle_timeout: load(&counters.closed_idle_timeout),
+                max_lifetime: load(&counters.closed_max_lifetime),
+                unhealthy: load(&counters.closed_unhealthy),
+                hook_rejected: load(&counters.closed_hook_rejected),
+            },
+            acquire_waits: load(&counters.acquire_waits),
+            acquire_wait_time: Duration::from_nanos(load(&counters.acquire_wait_nanos)),
+            leaked: load(&counters.leaked),
+            circuit: self.inner.breaker.as_ref().map(CircuitBreaker::state),
+        }
+    }
+
+    /// Returns true if the pool has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed.load(Ordering::Acquire)
+    }
+
+    /// Returns a helper that completes when `close()` begins.
+    pub fn close_event(&self) -> CloseEvent {
+        CloseEvent {
+            notify: self.inner.shutdown.clone(),
+            is_closed: self.inner.is_closed.clone(),
+        }
+    }
+
+    /// Return a checked-out resource to idle, or close it if it should not be reused.
+    async fn release(
+        inner: &PoolInner<T>,
+        mut live: Live<T>,
+        permit: tokio::sync::OwnedSemaphorePermit,
+    ) {
+        if inner.is_closed.load(Ordering::Acquire) {
+            let _ = live.resource.close().await;
+            drop(permit);
+            return;
+        }
+        let discard = if live.is_past_lifetime(inner.options.max_lifetime) {
+            inner.counters.expired(ExpiryReason::MaxLifetime);
+            Some(PoolEventKind::Expired(ExpiryReason::MaxLifetime))
+        } else if !inner
+            .passes_health_check(&live.resource, live.metadata())
+            .await
+        {
+            inner.record_unhealthy();
+            Some(PoolEventKind::HealthCheckFailed)
+        } else {
+            None
+        };
+        if let Some(kind) = discard {
+            inner.emit(kind, Some(&live.resource));
+            let _ = live.resource.close().await;
+            drop(permit);
+            return;
+        }
+        // Apply after_release hook if configured
+        if let Some(hooks) = &inner.hooks {
+            if let Some(cb) = &hooks.after_release {
+                let meta = live.metadata();
+                match cb(&mut live.resource, meta).await {
+                    Ok(true) => {}
+                    rejected => {
+                        if let Err(e) = rejected {
+                            inner.hook_failed(PoolHook::AfterRelease, &live.resource, &e);
+                        }
+                        PoolCounters::incr(&inner.counters.closed_hook_rejected);
+                        let _ = live.resource.close().await;
+                        drop(permit);
+                        return;
+                    }
+                }
+            }
+        }
+        inner.emit(PoolEventKind::Released, Some(&live.resource));
+        let mut idle = inner.idle.lock().await;
+        idle.push_back(live.into_idle());
+        drop(permit);
+        inner.idle_added();
+        debug!("resource returned to pool idle");
+    }
+
+    /// Requeue a returned resource without awaiting, if nothing about the return
+    /// needs async work and the idle queue is uncontended.
+    fn try_release_inline(inner: &PoolInner<T>, live: Live<T>) -> std::result::Result<(), Live<T>> {
+        let needs_async = inner.is_closed.load(Ordering::Acquire)
+            || live.is_past_lifetime(inner.options.max_lifetime)
+            || !inner.options.health_check.skips_release()
+            || inner
+                .hooks
+                .as_ref()
+                .is_some_and(|h| h.after_release.is_some());
+        if needs_async {
+            return Err(live);
+        }
+        let Ok(mut idle) = inner.idle.try_lock() else {
+            return Err(live);
+        };
+        inner.emit(PoolEventKind::Released, Some(&live.resource));
+        idle.push_back(live.into_idle());
+        inner.idle_added();
+        Ok(())
+    }
+
+    /// Run the `after_create` hook, closing the resource if it rejects.
+    async fn run_after_create(inner: &Arc<PoolInner<T>>, res: &mut T) -> Result<()> {
+        if let Some(hooks) = &inner.hooks {
+            if let Some(cb) = &hooks.after_create {
+                let meta = PoolConnectionMetadata {
+                    age: Duration::from_secs(0),
+                    idle_for: Duration::from_secs(0),
+                };
+                if let Err(e) = cb(res, meta).await {
+                    inner.hook_failed(PoolHook::AfterCreate, res, &e);
+                    PoolCounters::incr(&inner.counters.closed_hook_rejected);
+                    let _ = res.close().await;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Close one idle resource, oldest first. Returns false if none were idle.
+    async fn evict_one_idle(inner: &Arc<PoolInner<T>>) -> bool {
+        Self::evict_one_unmatched(inner, None).await
+    }
+
+    /// Close the oldest idle resource whose labels don't satisfy `keep`.
+    /// Returns false if there was none.
+    async fn evict_one_unmatched(inner: &Arc<PoolInner<T>>, keep: LabelFilter<'_>) -> bool {
+        let maybe = {
+            let mut idle = inner.idle.lock().await;
+            idle.iter()
+                .position(|i| !keep.is_some_and(|f| f(&i.live.labels)))
+                .and_then(|pos| idle.remove(pos))
+        };
+        match maybe {
+            Some(mut idle) => {
+                debug!(
+                    "evicting idle resource: {}",
+                    idle.live.resource.resource_id()
+                );
+                let _ = idle.live.resource.close().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resources currently open: idle plus checked out.
+    async fn live_count(inner: &Arc<PoolInner<T>>) -> usize {
+        let idle = inner.idle.lock().await.len();
+        let checked_out = inner.options.max_connections - inner.semaphore.available_permits();
+        idle + checked_out
+    }
+
+    /// Create resources via the stored factory until `min_connections` are open.
+    async fn maintain_min_connections(inner: &Arc<PoolInner<T>>) {
+        let Some(factory) = &inner.factory else {
+            return;
+        };
+        let min = inner
+            .options
+            .min_connections
+            .min(inner.options.max_connections);
+        while !inner.is_closed.load(Ordering::Acquire)
+            && inner.circuit_closed()
+            && Self::live_count(inner).await < min
+        {
+            // Hold a permit while creating so warm-up never exceeds max_connections.
+            let Ok(permit) = inner.semaphore.clone().try_acquire_owned() else {
+                return;
+            };
+            let Some(res) = Self::create_in_background(inner, factory, "warm-up").await else {
+                return;
+            };
+            inner
+                .idle
+                .lock()
+                .await
+                .push_back(inner.new_live(res).into_idle());
+            drop(permit);
+        }
+    }
+
+    /// Create a resource outside of any acquire, logging failures.
+    async fn create_in_background(
+        inner: &Arc<PoolInner<T>>,
+        factory: &ResourceFactory<T>,
+        purpose: &str,
+    ) -> Option<T> {
+        let mut res = match factory().await {
+            Ok(res) => res,
+            Err(e) => {
+                warn!("pool {}: factory failed: {}", purpose, e);
+                inner.record_create_failed();
+                return None;
+            }
+        };
+        if let Err(e) = Self::run_after_create(inner, &mut res).await {
+            warn!("pool {}: after_create rejected resource: {}", purpose, e);
+            return None;
+        }
+        debug!("pool {}: created resource: {}", purpose, res.resource_id());
+        PoolCounters::incr(&inner.counters.created);
+        inner.emit(PoolEventKind::Created, Some(&res));
+        Some(res)
+    }
+
+    /// Pop the next idle resource that is unexpired and passes its health check.
+    async fn pop_idle_healthy<'a>(
+        inner: &'a Arc<PoolInner<T>>,
+        filter: LabelFilter<'_>,
+    ) -> Option<(Claimed<'a, T>, PoolConnectionMetadata)> {
+        loop {
+            let maybe = {
+                let mut idle = inner.idle.lock().await;
+                let matches = |i: &Idle<T>| filter.is_none_or(|f| f(&i.live.labels));
+                let pos = match inner.options.reuse_strategy {
+                    ReuseStrategy::Fifo => idle.iter().position(matches),
+                    ReuseStrategy::Lifo => idle.iter().rposition(matches),
+                };
+                pos.and_then(|pos| idle.remove(pos))
+            };
+            let mut idle = maybe?;
+
+            let expiry = if idle.live.is_past_lifetime(inner.options.max_lifetime) {
+                Some(ExpiryReason::MaxLifetime)
+            } else if idle.is_past_idle_timeout(inner.options.idle_timeout) {
+                Some(ExpiryReason::IdleTimeout)
+            } else {
+                None
+            };
+            if let Some(reason) = expiry {
+                inner.counters.expired(reason);
+                inner.emit(PoolEventKind::Expired(reason), Some(&idle.live.resource));
+                let _ = idle.live.resource.close().await;
+                continue;
+            }
+            let meta = idle.metadata();
+            let mut claimed = Claimed::new(inner, idle.live, Some(idle.idle_since));
+            if inner.passes_health_check(claimed.resource(), meta).await {
+                return Some((claimed, meta));
+            }
+            let mut live = claimed.into_live();
+            inner.record_unhealthy();
+            inner.emit(PoolEventKind::HealthCheckFailed, Some(&live.resource));
+            let _ = live.resource.close().await;
+        }
+    }
+
+    /// Create a successor for an expiring resource, if `replace_before_expire` is on.
+    async fn create_replacement(inner: &Arc<PoolInner<T>>) -> Option<T> {
+        if !inner.options.replace_before_expire || !inner.circuit_closed() {
+            return None;
+        }
+        let factory = inner.factory.as_ref()?;
+        Self::create_in_background(inner, factory, "replacement").await
+    }
+
+    async fn cleanup_idle_with(inner: &Arc<PoolInner<T>>) {
+        // Take out only expired resources; the rest stay reusable meanwhile.
+        let expired: Vec<_> = {
+            let mut idle = inner.idle.lock().await;
+            // Idle timeouts never shrink the pool below min_connections.
+            let min = inner.options.min_connections;
+            let mut live =
+                idle.len() + (inner.options.max_connections - inner.semaphore.available_permits());
+            let mut expired = Vec::new();
+            let mut kept = VecDeque::with_capacity(idle.len());
+            for r in idle.drain(..) {
+                if r.live.is_past_lifetime(inner.options.max_lifetime) {
+                    // A successor, if any, takes its place in the count.
+                    if !inner.options.replace_before_expire {
+                        live -= 1;
+                    }
+                    expired.push((r, ExpiryReason::MaxLifetime));
+                } else if r.is_past_idle_timeout(inner.options.idle_timeout) && live > min {
+                    live -= 1;
+                    expired.push((r, ExpiryReason::IdleTimeout));
+                } else {
+                    kept.push_back(r);
+                }
+            }
+            *idle = kept;
+            expired
+        };
+        let mut successors = 0;
+        for (mut r, expiry) in expired {
+            if expiry == ExpiryReason::MaxLifetime {
+                if let Some(successor) = Self::create_replacement(inner).await {
+                    let successor = inner.new_live(successor).into_idle();
+                    inner.idle.lock().await.push_back(successor);
+                    successors += 1;
+                }
+            }
+            inner.counters.expired(expiry);
+            inner.emit(PoolEventKind::Expired(expiry), Some(&r.live.resource));
+            if let Err(e) = r.live.resource.close().await {
+                warn!("error closing idle resource: {}", e);
+            }
+        }
+
+        if matches!(inner.options.health_check, HealthCheckPolicy::Never) {
+            return;
+        }
+        // Health-check from the front of the queue one batch at a time, so
+        // acquires can still reuse everything outside the current batch.
+        // Survivors go to the back, which restores the order after a full pass.
+        let batch_size = inner.options.health_check_concurrency.max(1);
+        let mut unchecked = inner.idle.lock().await.len().saturating_sub(successors);
+        while unchecked > 0 {
+            let batch: Vec<_> = {
+                let mut idle = inner.idle.lock().await;
+                let n = batch_size.min(unchecked).min(idle.len());
+                idle.drain(..n).collect()
+            };
+            if batch.is_empty() {
+                break;
+            }
+            unchecked -= batch.len();
+            let checks = futures::stream::iter(batch).map(|mut r| async move {
+                if inner
+                    .passes_health_check(&r.live.resource, r.metadata())
+                    .await
+                {
+                    return Some(r);
+                }
+                inner.record_unhealthy();
+                inner.emit(PoolEventKind::HealthCheckFailed, Some(&r.live.resource));
+                if let Err(e) = r.live.resource.close().await {
+                    warn!("error closing idle resource: {}", e);
+                }
+                None
+            });
+            let keep: Vec<_> = checks
+                .buffered(batch_size)
+                .filter_map(std::future::ready)
+                .collect()
+                .await;
+            inner.idle.lock().await.extend(keep);
+            inner.idle_added();
+        }
+    }
+}
+
+impl<T: PoolableResource + 'static> Drop for Pool<T> {
+    fn drop(&mut self) {
+        // Best-effort: on last reference, signal shutdown and spawn async idle cleanup.
+        if Arc::strong_count(&self.inner) == 1 {
+            let inner = self.inner.clone();
+            tokio::spawn(async move {
+                inner.is_closed.store(true, Ordering::Release);
+                // Wake all waiters
+                inner.shutdown.notify_waiters();
+                if let Some(handle) = inner.maintenance_handle.lock().await.take() {
+                    let _ = tokio::time::timeout(Duration::from_secs(5), handle).await;
+                }
+                let mut idle = inner.idle.lock().await;
+                let all: Vec<_> = idle.drain(..).collect();
+                drop(idle);
+                for mut r in all {
+                    let _ = r.live.resource.close().await;
+                }
+                inner.emit(PoolEventKind::Closed, None);
+            });
+        }
+    }
+}
+
+/// Builder for a [`Pool`] that can own its resource factory.
+pub struct PoolBuilder<T: PoolableResource + 'static> {
+    options: PoolOptions,
+    hooks: Option<PoolHooks<T>>,
+    factory: Option<ResourceFactory<T>>,
+    metrics: Option<Arc<dyn PoolMetricsRecorder>>,
+    on_idle: Option<Arc<tokio::sync::Notify>>,
+}
+
+impl<T: PoolableResource + 'static> PoolBuilder<T> {
+    fn new() -> Self {
+        Self {
+            options: PoolOptions::default(),
+            hooks: None,
+            factory: None,
+            metrics: None,
+            on_idle: None,
+        }
+    }
+
+    /// Set pool options (defaults to `PoolOptions::default()`).
+    pub fn options(mut self, options: PoolOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set lifecycle hooks.
+    pub fn hooks(mut self, hooks: PoolHooks<T>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Set the factory used by [`Pool::acquire`] and warm-up.
+    pub fn factory<F, Fut>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.factory = Some(Arc::new(move || Box::pin(factory())));
+        self
+    }
+
+    /// Report pool activity to `recorder`, e.g. a [`MetricsRecorder`] per upstream.
+    pub fn metrics(mut self, recorder: Arc<dyn PoolMetricsRecorder>) -> Self {
+        self.metrics = Some(recorder);
+        self
+    }
+
+    /// Build the pool and start its maintenance task.
+    pub fn build(self) -> Pool<T> {
+        Pool::from_parts(self)
+    }
+}
+
+/// Receives pool activity for export as counters, gauges and histograms.
+///
+/// Every method defaults to a no-op, so implementations only override what
+/// they export. Methods are called inline on pool paths and must not block.
+pub trait PoolMetricsRecorder: Send + Sync {
+    /// A connection was checked out.
+    fn acquired(&self) {}
+    /// Time an `acquire` spent waiting for capacity (zero if none was needed).
+    fn acquire_wait(&self, _wait: Duration) {}
+    /// An `acquire` gave up waiting for capacity.
+    fn acquire_timed_out(&self) {}
+    /// A resource was created (acquire, warm-up or replacement).
+    fn created(&self) {}
+    /// The factory returned an error.
+    fn create_failed(&self) {}
+    /// A resource failed its health check and was closed.
+    fn health_check_failed(&self) {}
+    /// A resource was closed for exceeding a timeout.
+    fn expired(&self, _reason: ExpiryReason) {}
+    /// A connection was returned to idle.
+    fn released(&self) {}
+    /// Idle and checked-out counts, sampled on each maintenance tick.
+    fn connections(&self, _idle: usize, _active: usize) {}
+}
+
+/// [`PoolMetricsRecorder`] that records through the `metrics` crate, labelled
+/// with the pool's upstream so one exporter can scrape every pool.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone)]
+pub struct MetricsRecorder {
+    upstream: String,
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsRecorder {
+    pub fn new(upstream: impl Into<String>) -> Self {
+        Self {
+            upstream: upstream.into(),
+        }
+    }
+
+    fn count(&self, name: &'static str) {
+        metrics::counter!(name, "upstream" => self.upstream.clone()).increment(1);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl PoolMetricsRecorder for MetricsRecorder {
+    fn acquired(&self) {
+        self.count("shadowcat_pool_acquires_total");
+    }
+
+    fn acquire_wait(&self, wait: Duration) {
+        metrics::histogram!("shadowcat_pool_acquire_wait_seconds", "upstream" => self.upstream.clone())
+            .record(wait.as_secs_f64());
+    }
+
+    fn acquire_timed_out(&self) {
+        self.count("shadowcat_pool_acquire_timeouts_total");
+    }
+
+    fn created(&self) {
+        self.count("shadowcat_pool_created_total");
+    }
+
+    fn create_failed(&self) {
+        self.count("shadowcat_pool_create_failures_total");
+    }
+
+    fn health_check_failed(&self) {
+        self.count("shadowcat_pool_health_check_failures_total");
+    }
+
+    fn expired(&self, reason: ExpiryReason) {
+        let reason = match reason {
+            ExpiryReason::IdleTimeout => "idle_timeout",
+            ExpiryReason::MaxLifetime => "max_lifetime",
+        };
+        metrics::counter!(
+            "shadowcat_pool_expired_total",
+            "upstream" => self.upstream.clone(),
+            "reason" => reason
+        )
+        .increment(1);
+    }
+
+    fn released(&self) {
+        self.count("shadowcat_pool_released_total");
+    }
+
+    fn connections(&self, idle: usize, active: usize) {
+        metrics::gauge!("shadowcat_pool_idle_connections", "upstream" => self.upstream.clone())
+            .set(idle as f64);
+        metrics::gauge!("shadowcat_pool_active_connections", "upstream" => self.upstream.clone())
+            .set(active as f64);
+    }
+}
+
+/// A close event that fires when `Pool::close()` begins.
+pub struct CloseEvent {
+    notify: Arc<tokio::sync::Notify>,
+    is_closed: Arc<AtomicBool>,
+}
+
+impl CloseEvent {
+    /// Returns a future that completes when `Pool::close()` begins.
+    ///
+    /// If the pool is already closed, the returned future completes immediately.
+    /// Otherwise, it awaits an *owned* notification created before waiting,
+    /// avoiding any lifetime issues or double-poll pitfalls.
+    pub fn notified(&self) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if self.is_closed.load(Ordering::Acquire) {
+            Box::pin(async {})
+        } else {
+            Box::pin(self.notify.clone().notified_owned())
+        }
+    }
+
+    /// Convenience async wrapper for `.notified()`.
+    pub async fn wait(&self) {
+        self.notified().await;
+    }
+}
+
+/// Optional hooks to customize pool behavior, modeled after SQLx semantics.
+type HookUnit<T> = Arc<
+    dyn for<'a> Fn(
+            &'a mut T,
+            PoolConnectionMetadata,
+        ) -> Pin<Box<dyn Future<Output = crate::error::Result<()>> + Send + 'a>>
+        + Send
+        + Sync,
+>;
+type HookBool<T> = Arc<
+    dyn for<'a> Fn(
+            &'a mut T,
+            PoolConnectionMetadata,
+        ) -> Pin<Box<dyn Future<Output = crate::error::Result<bool>> + Send + 'a>>
+        + Send
+        + Sync,
+>;
+
+pub struct PoolHooks<T: PoolableResource + 'static> {
+    /// Called after creating a new resource (not for idle reuse). Return Err to reject and fail acquire.
+    pub after_create: Option<HookUnit<T>>,
+    /// Called before giving out an idle resource. Return Ok(false) or Err to reject; pool closes and tries next.
+    pub before_acquire: Option<HookBool<T>>,
+    /// Called before returning a resource to idle on drop. Return Ok(false) or Err to close instead of requeue.
+    pub after_release: Option<HookBool<T>>,
+    /// Called after `after_create` succeeds to label the new resource, e.g. with
+    /// the protocol version it negotiated. See [`Pool::acquire_matching`].
+    pub labels: Option<LabelFn<T>>,
+    /// Called when any hook above returns Err, before the resource is closed.
+    pub on_hook_error: Option<HookErrorFn>,
+}
+
+impl<T: PoolableResource + 'static> Default for PoolHooks<T> {
+    fn default() -> Self {
+        Self {
+            after_create: None,
+            before_acquire: None,
+            after_release: None,
+            labels: None,
+            on_hook_error: None,
+        }
+    }
+}
+
+/// Callback for [`PoolHooks::labels`].
+pub type LabelFn<T> = Arc<dyn Fn(&T) -> Labels + Send + Sync>;
+
+/// Callback for [`PoolHooks::on_hook_error`].
+pub type HookErrorFn = Arc<dyn Fn(&HookError<'_>) + Send + Sync>;
+
+/// Which hook failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolHook {
+    AfterCreate,
+    BeforeAcquire,
+    AfterRelease,
+}
+
+/// A hook returned Err; passed to [`PoolHooks::on_hook_error`].
+#[derive(Debug)]
+pub struct HookError<'a> {
+    pub hook: PoolHook,
+    pub resource_id: String,
+    pub error: &'a ShadowcatError,
+}
+
+/// Metadata passed to hooks.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolConnectionMetadata {
+    pub age: Duration,
+    pub idle_for: Duration,
+}
+
+/// Handle to a resource checked out from the pool.
+pub struct PoolConnection<T: PoolableResource + 'static> {
+    resource: Option<Live<T>>,
+    pool: Pool<T>,
+    permit: Option<tokio::sync::OwnedSemaphorePermit>,
+    /// Key into the pool's checkout registry when leak detection is on.
+    checkout: Option<u64>,
+}
+
+impl<T: PoolableResource + 'static> PoolConnection<T> {
+    /// Access the underlying resource mutably.
+    pub fn resource(&mut self) -> &mut T {
+        &mut self.resource.as_mut().expect("resource present").resource
+    }
+
+    /// Labels the pool keeps for this resource.
+    pub fn labels(&self) -> &Labels {
+        &self.resource.as_ref().expect("resource present").labels
+    }
+
+    /// Set a label that stays with the resource when it returns to idle.
+    pub fn set_label(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.resource
+            .as_mut()
+            .expect("resource present")
+            .labels
+            .insert(key.into(), value.into());
+    }
+
+    /// Take ownership of the resource and give its capacity back to the pool.
+    ///
+    /// The resource is never requeued or closed by the pool; the caller is
+    /// responsible for closing it.
+    pub fn detach(mut self) -> T {
+        let live = self.resource.take().expect("resource present");
+        drop(self.permit.take());
+        self.pool.inner.checked_in();
+        self.pool
+            .inner
+            .emit(PoolEventKind::Detached, Some(&live.resource));
+        live.resource
+    }
+}
+
+impl<T: PoolableResource + 'static> Drop for PoolConnection<T> {
+    fn drop(&mut self) {
+        if let Some(id) = self.checkout.take() {
+            self.pool.inner.checkouts.lock().unwrap().open.remove(&id);
+        }
+        let (Some(live), Some(permit)) = (self.resource.take(), self.permit.take()) else {
+            return;
+        };
+        let inner = &self.pool.inner;
+        // Release capacity only AFTER requeue, so a woken waiter finds the resource idle.
+        let live = match Pool::try_release_inline(inner, live) {
+            Ok(()) => {
+                drop(permit);
+                inner.checked_in();
+                return;
+            }
+            Err(live) => live,
+        };
+        if let Err(mpsc::error::SendError(Returned::Release(live, permit))) =
+            inner.returns.send(Returned::Release(live, permit))
+        {
+            // Maintenance has stopped; release in a task of our own.
+            let inner = inner.clone();
+            tokio::spawn(async move {
+                Pool::release(&inner, live, permit).await;
+                inner.checked_in();
+            });
+        }
+    }
+}
+
+/// Pool statistics snapshot.
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    pub idle: u64,
+    pub max: u64,
+    pub closed: bool,
+    /// Connections currently checked out.
+    pub active: u64,
+    /// Resources created over the pool's lifetime (acquire and warm-up).
+    pub total_created: u64,
+    /// Resources the pool closed instead of reusing, by reason.
+    pub total_closed: ClosedStats,
+    /// Acquires that found no free capacity and had to wait.
+    pub acquire_waits: u64,
+    /// Cumulative time spent in those waits.
+    pub acquire_wait_time: Duration,
+    /// Connections reported as held longer than `leak_timeout`.
+    pub leaked: u64,
+    /// Circuit breaker state, if one is configured.
+    pub circuit: Option<CircuitState>,
+}
+
+/// Resources closed by the pool, split by reason.
+///
+/// Excludes resources closed by `close()` or pool shutdown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClosedStats {
+    pub idle_timeout: u64,
+    pub max_lifetime: u64,
+    pub unhealthy: u64,
+    pub hook_rejected: u64,
+}
+
+impl ClosedStats {
+    pub fn total(&self) -> u64 {
+        self.idle_timeout + self.max_lifetime + self.unhealthy + self.hook_rejected
+    }
+}
+
+/// A pool lifecycle event; see [`Pool::events`].
+#[derive(Debug, Clone)]
+pub struct PoolEvent {
+    pub kind: PoolEventKind,
+    /// `resource_id()` of the affected resource, if the event concerns one.
+    pub resource_id: Option<String>,
+    pub at: SystemTime,
+}
+
+/// What happened in a [`PoolEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolEventKind {
+    /// A resource was created by a factory (acquire or warm-up).
+    Created,
+    /// An idle resource was handed out.
+    Reused,
+    /// A resource failed `is_healthy()` and was closed.
+    HealthCheckFailed,
+    /// A resource was closed for exceeding a configured timeout.
+    Expired(ExpiryReason),
+    /// A checked-out resource was returned to idle.
+    Released,
+    /// A checked-out resource was taken out of the pool with `detach()`.
+    Detached,
+    /// The pool was closed.
+    Closed,
+    /// An acquire gave up waiting for capacity.
+    AcquireTimeout,
+    /// The circuit breaker moved to a new state.
+    CircuitStateChanged(CircuitState),
+}
+
+/// Which limit expired a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryReason {
+    IdleTimeout,
+    MaxLifetime,
+}
+
+/// Options for a [`KeyedPool`].
+#[derive(Debug, Clone)]
+pub struct KeyedPoolOptions {
+    /// Options applied to every per-key sub-pool. `min_connections` and
+    /// `replace_before_expire` are ignored: sub-pools only create resources
+    /// for an acquire, within `max_total_connections`.
+    pub per_key: PoolOptions,
+    /// Budget shared by all keys: checked-out plus idle resources.
+    pub max_total_connections: usize,
+}
+
+impl Default for KeyedPoolOptions {
+    fn default() -> Self {
+        Self {
+            per_key: PoolOptions::default(),
+            max_total_connections: 100,
+        }
+    }
+}
+
+/// Factory a [`KeyedPool`] uses to create a resource for a given key.
+pub type KeyedResourceFactory<K, T> =
+    Arc<dyn Fn(&K) -> Pin<Box<dyn Future<Output = Result<T>> + Send>> + Send + Sync>;
+
+/// Builds the [`PoolMetricsRecorder`] for a [`KeyedPool`]'s sub-pool, given
+/// its key.
+pub type KeyedMetricsFactory<K> = Arc<dyn Fn(&K) -> Arc<dyn PoolMetricsRecorder> + Send + Sync>;
+
+struct KeyedPoolInner<K, T: PoolableResource + 'static> {
+    options: KeyedPoolOptions,
+    pools: std::sync::Mutex<HashMap<K, Pool<T>>>,
+    /// One permit per open resource, held by the resource until it closes.
+    global: Arc<Semaphore>,
+    /// Notified when any sub-pool puts a resource back to idle.
+    idle_added: Arc<tokio::sync::Notify>,
+    factory: KeyedResourceFactory<K, T>,
+    metrics: Option<KeyedMetricsFactory<K>>,
+    is_closed: AtomicBool,
+    shutdown: Arc<tokio::sync::Notify>,
+    maintenance_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+/// A pool partitioned by key (e.g. upstream origin), with one lazily created
+/// sub-pool per key and a global connection budget shared across keys.
+///
+/// Every open resource, idle or checked out, holds one slot of the budget. An
+/// acquire that has to create while the budget is used up closes an idle
+/// resource of another key, or waits for one to become idle.
+///
+/// Maintenance removes sub-pools that have no idle or checked-out resources.
+pub struct KeyedPool<K, T: PoolableResource + 'static>
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+{
+    inner: Arc<KeyedPoolInner<K, T>>,
+}
+
+impl<K, T> Clone for KeyedPool<K, T>
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    T: PoolableResource + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, T> KeyedPool<K, T>
+where
+    K: Clone + Eq + Hash + Send + Sync + 'static,
+    T: PoolableResource + 'static,
+{
+    /// Create a keyed pool; `factory` is called with the key a resource is for.
+    pub fn new<F, Fut>(options: KeyedPoolOptions, factory: F) -> Self
+    where
+        F: Fn(&K) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        Self::from_parts(options, factory, None)
+    }
+
+    /// Like [`KeyedPool::new`], but each sub-pool records metrics through the
+    /// recorder `metrics` returns for its key.
+    pub fn new_with_metrics<F, Fut>(
+        options: KeyedPoolOptions,
+        factory: F,
+        metrics: KeyedMetricsFactory<K>,
+    ) -> Self
+    where
+        F: Fn(&K) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        Self::from_parts(options, factory, Some(metrics))
+    }
+
+    fn from_parts<F, Fut>(
+        options: KeyedPoolOptions,
+        factory: F,
+        metrics: Option<KeyedMetricsFactory<K>>,
+    ) -> Self
+    where
+        F: Fn(&K) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let inner = Arc::new(KeyedPoolInner {
+            global: Arc::new(Semaphore::new(options.max_total_connections)),
+            idle_added: Arc::new(tokio::sync::Notify::new()),
+            options,
+            pools: std::sync::Mutex::new(HashMap::new()),
+            factory: Arc::new(move |key: &K| Box::pin(factory(key))),
+            metrics,
+            is_closed: AtomicBool::new(false),
+            shutdown: Arc::new(tokio::sync::Notify::new()),
+            maintenance_handle: Mutex::new(None),
+        });
+
+        let handle = Self::spawn_maintenance(&inner);
+        match inner.maintenance_handle.try_lock() {
+            Ok(mut guard) => {
+                *guard = Some(handle);
+            }
+            Err(_) => {
+                let inner_c = inner.clone();
+                tokio::spawn(async move {
+                    let mut guard = inner_c.maintenance_handle.lock().await;
+                    *guard = Some(handle);
+                });
+            }
+        }
+
+        Self { inner }
+    }
+
+    fn spawn_maintenance(inner: &Arc<KeyedPoolInner<K, T>>) -> tokio::task::JoinHandle<()> {
+        let weak = Arc::downgrade(inner);
+        let shutdown = inner.shutdown.clone();
+        let period = inner.options.per_key.health_check_interval;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            // absorb immediate tick
+            interval.tick().await;
+            loop {
+                let notified = shutdown.notified();
+                match weak.upgrade() {
+                    Some(inner) if !inner.is_closed.load(Ordering::Acquire) => {}
+                    _ => break,
+                }
+                tokio::select! {
+                    _ = notified => {
+                        trace!("keyed pool maintenance: shutdown");
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let Some(inner) = weak.upgrade() else {
+                            break;
+                        };
+                        trace!("keyed pool maintenance: tick");
+                        Self::evict_empty_keys(&inner).await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Acquire a resource for `key`, creating the key's sub-pool on first use.
+    pub async fn acquire(&self, key: &K) -> Result<KeyedPoolConnection<T>> {
+        if self.inner.is_closed.load(Ordering::Acquire) {
+            return Err(ShadowcatError::Protocol("Pool closed".into()));
+        }
+
+        let started = Instant::now();
+        let deadline = started + self.inner.options.per_key.acquire_timeout;
+        let pool = self.pool_for(key);
+        let create = pool.create_from_factory()?;
+        // The key's own capacity first; budget is only needed to create.
+        let permit = pool.wait_for_permit(Priority::Normal).await?;
+        if let Some(metrics) = &pool.inner.metrics {
+            metrics.acquire_wait(started.elapsed());
+        }
+        let budget = loop {
+            if let Some(live) = pool.reuse_idle(None).await {
+                return Ok(KeyedPoolConnection {
+                    conn: pool.connection(live, permit),
+                });
+            }
+            let idle_added = self.inner.idle_added.notified();
+            let shutdown = self.inner.shutdown.notified();
+            tokio::pin!(idle_added, shutdown);
+            idle_added.as_mut().enable();
+            shutdown.as_mut().enable();
+            if self.inner.is_closed.load(Ordering::Acquire) {
+                return Err(ShadowcatError::Protocol("Pool closed".into()));
+            }
+            if let Ok(budget) = self.inner.global.clone().try_acquire_owned() {
+                break budget;
+            }
+            if self.evict_idle_elsewhere(&pool).await {
+                continue;
+            }
+            tokio::select! {
+                res = self.inner.global.clone().acquire_owned() => {
+                    break res.map_err(|_| ShadowcatError::PoolExhausted)?;
+                }
+                _ = &mut idle_added => {}
+                _ = &mut shutdown => {
+                    return Err(ShadowcatError::Protocol("Pool closed".into()));
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(ShadowcatError::Timeout("Pool acquire timeout".into()));
+                }
+            }
+        };
+        let admission = pool.inner.admit()?;
+        let mut live = pool.create(move || create).await?;
+        admission.succeeded();
+        live.budget = Some(budget);
+        Ok(KeyedPoolConnection {
+            conn: pool.connection(live, permit),
+        })
+    }
+
+    /// Number of keys that currently have a sub-pool.
+    pub fn len(&self) -> usize {
+        self.inner.pools.lock().expect("keyed pool lock").len()
+    }
+
+    /// Returns true if no key currently has a sub-pool.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stats for every live sub-pool.
+    pub async fn stats(&self) -> HashMap<K, PoolStats> {
+        let mut out = HashMap::new();
+        for (key, pool) in self.snapshot() {
+            out.insert(key, pool.stats().await);
+        }
+        out
+    }
+
+    /// Close every sub-pool and stop maintenance.
+    pub async fn close(&self) {
+        self.inner.is_closed.store(true, Ordering::Release);
+        self.inner.shutdown.notify_waiters();
+        if let Some(handle) = self.inner.maintenance_handle.lock().await.take() {
+            let _ = handle.await;
+        }
+        let pools: Vec<_> = {
+            let mut pools = self.inner.pools.lock().expect("keyed pool lock");
+            pools.drain().map(|(_, pool)| pool).collect()
+        };
+        for pool in pools {
+            pool.close().await;
+        }
+    }
+
+    fn pool_for(&self, key: &K) -> Pool<T> {
+        let mut pools = self.inner.pools.lock().expect("keyed pool lock");
+        pools
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let factory = self.inner.factory.clone();
+                let key = key.clone();
+                let options = PoolOptions {
+                    min_connections: 0,
+                    replace_before_expire: false,
+                    ..self.inner.options.per_key.clone()
+                };
+                let metrics = self.inner.metrics.as_ref().map(|m| m(&key));
+                let mut builder = Pool::builder()
+                    .options(options)
+                    .factory(move || factory(&key));
+                builder.metrics = metrics;
+                builder.on_idle = Some(self.inner.idle_added.clone());
+                builder.build()
+            })
+            .clone()
+    }
+
+    fn snapshot(&self) -> Vec<(K, Pool<T>)> {
+        let pools = self.inner.pools.lock().expect("keyed pool lock");
+        pools.iter().map(|(k, p)| (k.clone(), p.clone())).collect()
+    }
+
+    /// Close one idle resource of a key other than `target`, freeing its
+    /// budget slot.
+    async fn evict_idle_elsewhere(&self, target: &Pool<T>) -> bool {
+        for (_, pool) in self.snapshot() {
+            if !Arc::ptr_eq(&pool.inner, &target.inner) && Pool::evict_one_idle(&pool.inner).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn evict_empty_keys(inner: &Arc<KeyedPoolInner<K, T>>) {
+        let candidates: Vec<_> = {
+            let pools = inner.pools.lock().expect("keyed pool lock");
+            pools.iter().map(|(k, p)| (k.clone(), p.clone())).collect()
+        };
+        let mut empty = Vec::new();
+        for (key, pool) in candidates {
+            if Pool::live_count(&pool.inner).await == 0 {
+                empty.push(key);
+            }
+        }
+        let removed: Vec<_> = {
+            let mut pools = inner.pools.lock().expect("keyed pool lock");
+            empty
+                .into_iter()
+                .filter_map(|key| {
+                    // Only the map may hold the sub-pool; any other handle
+                    // (a checked-out connection or an acquire in progress) keeps it.
+                    let unused = pools
+                        .get(&key)
+                        .is_some_and(|pool| Arc::strong_count(&pool.inner) == 1);
+                    if unused {
+                        pools.remove(&key)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        };
+        for pool in removed {
+            trace!("keyed pool maintenance: evicting empty key");
+            pool.close().await;
+        }
+    }
+}
+
+/// Handle to a resource checked out from a [`KeyedPool`].
+pub struct KeyedPoolConnection<T: PoolableResource + 'static> {
+    conn: PoolConnection<T>,
+}
+
+impl<T: PoolableResource + 'static> KeyedPoolConnection<T> {
+    /// Access the underlying resource mutably.
+    pub fn resource(&mut self) -> &mut T {
+        self.conn.resource()
+    }
+
+    /// Take ownership of the resource, releasing both its per-key and global capacity.
+    pub fn detach(self) -> T {
+        self.conn.detach()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct TestResource {
+        id: String,
+        healthy: Arc<AtomicBool>,
+        closed: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl PoolableResource for TestResource {
+        async fn is_healthy(&self) -> bool {
+            self.healthy.load(std::sync::atomic::Ordering::Relaxed)
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.closed
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn resource_id(&self) -> String {
+            self.id.clone()
+        }
+    }
+
+    fn make_options() -> PoolOptions {
+        PoolOptions {
+            max_connections: 1,
+            min_connections: 0,
+            acquire_timeout: Duration::from_millis(200),
+            idle_timeout: Some(Duration::from_millis(200)),
+            max_lifetime: Some(Duration::from_secs(60)),
+            health_check_interval: Duration::from_millis(50),
+            reuse_strategy: ReuseStrategy::Fifo,
+            starvation_timeout: Duration::from_secs(1),
+            health_check: HealthCheckPolicy::default(),
+            health_check_timeout: Some(Duration::from_secs(5)),
+            health_check_concurrency: 8,
+            leak_timeout: None,
+            create_retry: None,
+            circuit_breaker: None,
+            replace_before_expire: false,
+        }
+    }
+
+    fn counting_factory(created: Arc<AtomicUsize>) -> ResourceFactory<TestResource> {
+        Arc::new(move || {
+            let created = created.clone();
+            Box::pin(async move {
+                let n = created.fetch_add(1, Ordering::SeqCst);
+                Ok(TestResource {
+                    id: format!("warm-{n}"),
+                    healthy: Arc::new(AtomicBool::new(true)),
+                    closed: Arc::new(AtomicBool::new(false)),
+                })
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_acquire_and_reuse() {
+        let pool = Pool::<TestResource>::new(make_options());
+        let healthy = Arc::new(AtomicBool::new(true));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let mut ids = Vec::new();
+
+        // First acquire creates a resource
+        {
+            let mut c = pool
+                .acquire_with({
+                    let healthy = healthy.clone();
+                    let closed = closed.clone();
+                    move || {
+                        let healthy = healthy.clone();
+                        let closed = closed.clone();
+                        async move {
+                            Ok(TestResource {
+                                id: "res-1".into(),
+                                healthy,
+                                closed,
+                            })
+                        }
+                    }
+                })
+                .await
+                .expect("acquire should succeed");
+            ids.push(c.resource().resource_id());
+        } // drop returns to idle
+
+        // Second acquire should reuse
+        {
+            let mut c = pool
+                .acquire_with(|| async {
+                    Err::<TestResource, ShadowcatError>(ShadowcatError::Protocol(
+                        "should not create".into(),
+                    ))
+                })
+                .await
+                .expect("reuse should succeed");
+            ids.push(c.resource().resource_id());
+        }
+
+        assert_eq!(ids[0], ids[1], "resource should be reused");
+        let stats = pool.stats().await;
+        assert!(stats.idle <= 1);
+        assert_eq!(stats.max, 1);
+        assert!(!stats.closed);
+    }
+
+    #[tokio::test]
+    async fn test_close_marks_closed_and_drains_idle() {
+        let pool = Pool::<TestResource>::new(make_options());
+        let healthy = Arc::new(AtomicBool::new(true));
+        let closed_flag = Arc::new(AtomicBool::new(false));
+
+        // acquire and drop once to populate idle
+        {
+            let _c = pool
+                .acquire_with({
+                    let healthy = healthy.clone();
+                    let closed_flag = closed_flag.clone();
+                    move || {
+                        let healthy = healthy.clone();
+                        let closed_flag = closed_flag.clone();
+                        async move {
+                            Ok(TestResource {
+                                id: "x".into(),
+                                healthy,
+                                closed: closed_flag,
+                            })
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+        }
+
+        pool.close().await;
+        let stats_after = pool.stats().await;
+        assert!(stats_after.closed);
+        assert!(
+            closed_flag.load(Ordering::Relaxed),
+            "resource should be closed during pool.close()"
+        );
+
+        // Further acquires should fail fast
+        let res = pool.acquire_with(|| async { unreachable!() }).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_cleanup() {
+        let mut options = make_options();
+        options.idle_timeout = Some(Duration::from_millis(50));
+        options.health_check_interval = Duration::from_millis(30);
+        let pool = Pool::<TestResource>::new(options);
+        let healthy = Arc::new(AtomicBool::new(true));
+        let closed_flag = Arc::new(AtomicBool::new(false));
+
+        {
+            let _c = pool
+                .acquire_with({
+                    let healthy = healthy.clone();
+                    let closed_flag = closed_flag.clone();
+                    move || {
+                        let healthy = healthy.clone();
+                        let closed_flag = closed_flag.clone();
+                        async move {
+                            Ok(TestResource {
+                                id: "y".into(),
+                                healthy,
+                                closed: closed_flag,
+                            })
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+        }
+
+        // Wait enough for idle timeout + maintenance tick
+        tokio::time::sleep(Duration::from_millis(120)).await;
+
+        // Acquire again should create a new resource as old idle was cleaned
+        let new = pool
+            .acquire_with({
                 let healthy = Arc::new(AtomicBool::new(true));
                 let closed = Arc::new(AtomicBool::new(false));
                 move || {
                     let healthy = healthy.clone();
-                    let closed = closed.clone();
+                    let closed = closed.clone();
+                    async move {
+                        Ok(TestResource {
+                            id: "z".into(),
+                            healthy,
+                            closed,
+                        })
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert!(
+            closed_flag.load(Ordering::Relaxed),
+            "old resource should have been closed by cleanup"
+        );
+        drop(new);
+    }
+
+    #[tokio::test]
+    async fn test_permit_released_after_requeue() {
+        // With max_connections=1, second acquire should wait until first is dropped.
+        let pool = Pool::<TestResource>::new(make_options());
+        let healthy = Arc::new(AtomicBool::new(true));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let conn1 = pool
+            .acquire_with({
+                let healthy = healthy.clone();
+                let closed = closed.clone();
+                move || {
+                    let healthy = healthy.clone();
+                    let closed = closed.clone();
+                    async move {
+                        Ok(TestResource {
+                            id: "one".into(),
+                            healthy,
+                            closed,
+                        })
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        // Start second acquire which should block until conn1 is dropped
+        let pool2 = pool.clone();
+        let task = tokio::spawn(async move {
+            pool2
+                .acquire_with(|| async {
+                    Ok(TestResource {
+                        id: "two".into(),
+                        healthy: Arc::new(AtomicBool::new(true)),
+                        closed: Arc::new(AtomicBool::new(false)),
+                    })
+                })
+                .await
+        });
+
+        // Give it a moment to attempt acquire (should be pending)
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!task.is_finished());
+
+        drop(conn1); // triggers return-to-idle and then releases permit
+        let res = tokio::time::timeout(Duration::from_millis(300), task).await;
+        assert!(
+            res.is_ok(),
+            "second acquire should complete after first drop"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_acquire_cancels_on_close() {
+        // With max_connections=1, second acquire should be pending; closing should cancel it promptly.
+        let pool = Pool::<TestResource>::new(make_options());
+        let healthy = Arc::new(AtomicBool::new(true));
+        let closed = Arc::new(AtomicBool::new(false));
+
+        // Hold first connection to exhaust capacity
+        let _conn = pool
+            .acquire_with({
+                let healthy = healthy.clone();
+                let closed = closed.clone();
+                move || {
+                    let healthy = healthy.clone();
+                    let closed = closed.clone();
+                    async move {
+                        Ok(TestResource {
+                            id: "held".into(),
+                            healthy,
+                            closed,
+                        })
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        // Start a second acquire that will block on the semaphore
+        let pool2 = pool.clone();
+        let pending = tokio::spawn(async move {
+            pool2
+                .acquire_with(|| async { unreachable!("should not construct while closed") })
+                .await
+        });
+
+        // Ensure it's pending
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!pending.is_finished());
+
+        // Close the pool; this should cancel the pending acquire promptly
+        let pool3 = pool.clone();
+        let closer = tokio::spawn(async move { pool3.close().await });
+
+        let res = tokio::time::timeout(Duration::from_millis(200), pending).await;
+        assert!(
+            res.is_ok(),
+            "pending acquire should resolve promptly after close starts"
+        );
+        let join = res.unwrap();
+        assert!(join.is_ok(), "task should not panic");
+        let inner = join.unwrap();
+        assert!(inner.is_err(), "acquire should error due to pool close");
+
+        // Ensure close completes
+        let _ = closer.await;
+    }
+
+    #[tokio::test]
+    async fn test_close_event_notifies_on_close() {
+        let pool = Pool::<TestResource>::new(make_options());
+        let evt = pool.close_event();
+
+        let (armed_tx, armed_rx) = tokio::sync::oneshot::channel();
+
+        // Construct the owned waiter first; then signal we're armed; then await it.
+        let waiter = tokio::spawn(async move {
+            let fut = evt.notified();
+            let _ = armed_tx.send(());
+            fut.await;
+        });
+
+        // Wait until the waiter has created the future, so we can't miss the notify.
+        let _ = armed_rx.await;
+
+        // Now close can't race - the waiter is registered (or will complete immediately).
+        pool.close().await;
+
+        let done = tokio::time::timeout(Duration::from_millis(300), waiter).await;
+        assert!(
+            done.is_ok(),
+            "close_event waiter should complete after close"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pop_idle_filters_and_closes_stale() {
+        let options = PoolOptions {
+            max_connections: 1,
+            min_connections: 0,
+            acquire_timeout: Duration::from_millis(200),
+            idle_timeout: Some(Duration::from_millis(30)),
+            max_lifetime: Some(Duration::from_secs(60)),
+            health_check_interval: Duration::from_millis(500),
+            reuse_strategy: ReuseStrategy::Fifo,
+            starvation_timeout: Duration::from_secs(1),
+            health_check: HealthCheckPolicy::default(),
+            health_check_timeout: Some(Duration::from_secs(5)),
+            health_check_concurrency: 8,
+            leak_timeout: None,
+            create_retry: None,
+            circuit_breaker: None,
+            replace_before_expire: false,
+        };
+        let pool = Pool::<TestResource>::new(options);
+        let healthy = Arc::new(AtomicBool::new(true));
+        let stale_closed = Arc::new(AtomicBool::new(false));
+
+        // Create one resource and drop to idle
+        {
+            let _c = pool
+                .acquire_with({
+                    let healthy = healthy.clone();
+                    let stale_closed = stale_closed.clone();
+                    move || {
+                        let healthy = healthy.clone();
+                        let stale_closed = stale_closed.clone();
+                        async move {
+                            Ok(TestResource {
+                                id: "old".into(),
+                                healthy,
+                                closed: stale_closed,
+                            })
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+        }
+
+        // Sleep past idle_timeout but before maintenance runs
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // Next acquire should filter the stale idle (and close it) and create new
+        let mut conn = pool
+            .acquire_with({
+                let healthy = Arc::new(AtomicBool::new(true));
+                let new_closed = Arc::new(AtomicBool::new(false));
+                move || {
+                    let healthy = healthy.clone();
+                    let new_closed = new_closed.clone();
+                    async move {
+                        Ok(TestResource {
+                            id: "new".into(),
+                            healthy,
+                            closed: new_closed,
+                        })
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(conn.resource().resource_id(), "new");
+        assert!(
+            stale_closed.load(Ordering::Relaxed),
+            "stale idle should have been closed by pop_idle_healthy"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_before_acquire_rejects_idle_and_creates_new() {
+        // Prepare a pool with before_acquire hook that rejects id == "bad"
+        let hooks = PoolHooks::<TestResource> {
+            before_acquire: Some(Arc::new(
+                |r: &mut TestResource, _meta: PoolConnectionMetadata| {
+                    let id = r.id.clone();
+                    Box::pin(async move { Ok(id != "bad") })
+                },
+            )),
+            ..Default::default()
+        };
+        let pool = Pool::<TestResource>::new_with_hooks(make_options(), hooks);
+
+        let healthy = Arc::new(AtomicBool::new(true));
+        let bad_closed = Arc::new(AtomicBool::new(false));
+        let good_closed = Arc::new(AtomicBool::new(false));
+
+        // First acquire a BAD resource and drop to idle
+        {
+            let _c = pool
+                .acquire_with({
+                    let healthy = healthy.clone();
+                    let bad_closed = bad_closed.clone();
+                    move || {
+                        let healthy = healthy.clone();
+                        let bad_closed = bad_closed.clone();
+                        async move {
+                            Ok(TestResource {
+                                id: "bad".into(),
+                                healthy,
+                                closed: bad_closed,
+                            })
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+        }
+
+        // Now acquire again; hook should reject idle "bad" and factory creates "good"
+        let mut conn = pool
+            .acquire_with({
+                let healthy = healthy.clone();
+                let good_closed = good_closed.clone();
+                move || {
+                    let healthy = healthy.clone();
+                    let good_closed = good_closed.clone();
                     async move {
                         Ok(TestResource {
-                            id: "z".into(),
+                            id: "good".into(),
                             healthy,
-                            closed,
+                            closed: good_closed,
+                        })
+                    }
+                }
+            })
+            .await
+            .expect("acquire should succeed with new resource after rejection");
+        assert_eq!(conn.resource().resource_id(), "good");
+        assert!(
+            bad_closed.load(Ordering::Relaxed),
+            "rejected idle resource should be closed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_after_release_rejects_return() {
+        // Hook that closes on release (return false)
+        let hooks = PoolHooks::<TestResource> {
+            after_release: Some(Arc::new(
+                |_r: &mut TestResource, _meta: PoolConnectionMetadata| {
+                    Box::pin(async move { Ok(false) })
+                },
+            )),
+            ..Default::default()
+        };
+        let pool = Pool::<TestResource>::new_with_hooks(make_options(), hooks);
+
+        let healthy = Arc::new(AtomicBool::new(true));
+        let closed_a = Arc::new(AtomicBool::new(false));
+
+        // Acquire and drop; after_release should cause close instead of requeue
+        {
+            let _c = pool
+                .acquire_with({
+                    let healthy = healthy.clone();
+                    let closed_a = closed_a.clone();
+                    move || {
+                        let healthy = healthy.clone();
+                        let closed_a = closed_a.clone();
+                        async move {
+                            Ok(TestResource {
+                                id: "a".into(),
+                                healthy,
+                                closed: closed_a,
+                            })
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+        }
+        // Give drop task time to run
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let stats = pool.stats().await;
+        assert_eq!(stats.idle, 0, "resource should not be returned to idle");
+        assert!(
+            closed_a.load(Ordering::Relaxed),
+            "resource should be closed by after_release"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_min_connections_warm_up() {
+        let mut options = make_options();
+        options.max_connections = 3;
+        options.min_connections = 2;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created.clone()));
+
+        // Warm-up runs as soon as maintenance starts, before the first tick.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.stats().await.idle, 2);
+
+        // Acquires are served from the warm resources.
+        let _c = pool
+            .acquire_with(|| async { unreachable!("warm resource should be reused") })
+            .await
+            .unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_keeps_min_connections() {
+        let mut options = make_options();
+        options.max_connections = 2;
+        options.min_connections = 1;
+        options.idle_timeout = Some(Duration::from_millis(20));
+        options.health_check_interval = Duration::from_millis(30);
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created.clone()));
+
+        // Several maintenance ticks past the idle timeout.
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(
+            created.load(Ordering::SeqCst),
+            1,
+            "the minimum resource should survive idle timeouts"
+        );
+        assert_eq!(pool.stats().await.idle, 1);
+    }
+
+    #[tokio::test]
+    async fn test_builder_factory_acquire() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(make_options())
+            .factory({
+                let created = created.clone();
+                move || {
+                    let created = created.clone();
+                    async move {
+                        created.fetch_add(1, Ordering::SeqCst);
+                        Ok(TestResource {
+                            id: "built".into(),
+                            healthy: Arc::new(AtomicBool::new(true)),
+                            closed: Arc::new(AtomicBool::new(false)),
                         })
                     }
                 }
             })
+            .build();
+
+        {
+            let mut c = pool.acquire().await.expect("factory acquire");
+            assert_eq!(c.resource().resource_id(), "built");
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let _c = pool.acquire().await.expect("reuse acquire");
+        assert_eq!(created.load(Ordering::SeqCst), 1, "idle resource reused");
+    }
+
+    #[tokio::test]
+    async fn test_acquire_without_factory_errors() {
+        let pool = Pool::<TestResource>::new(make_options());
+        assert!(pool.acquire().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hooks_receive_real_age_and_idle_time() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hooks = PoolHooks::<TestResource> {
+            before_acquire: Some(Arc::new({
+                let seen = seen.clone();
+                move |_r: &mut TestResource, meta: PoolConnectionMetadata| {
+                    seen.lock().unwrap().push(meta);
+                    Box::pin(async move { Ok(true) })
+                }
+            })),
+            ..Default::default()
+        };
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(make_options())
+            .hooks(hooks)
+            .factory({
+                let created = created.clone();
+                move || counting_factory(created.clone())()
+            })
+            .build();
+
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        drop(pool.acquire().await.unwrap());
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert!(seen[0].age >= Duration::from_millis(40));
+        assert!(seen[0].idle_for >= Duration::from_millis(30));
+        assert!(seen[0].age >= seen[0].idle_for);
+    }
+
+    #[tokio::test]
+    async fn test_max_lifetime_uses_creation_time() {
+        let mut options = make_options();
+        options.max_lifetime = Some(Duration::from_millis(50));
+        options.health_check_interval = Duration::from_secs(60);
+        let pool = Pool::<TestResource>::new(options);
+        let closed = Arc::new(AtomicBool::new(false));
+
+        // Hold the resource past its lifetime; it has never been idle.
+        let conn = pool
+            .acquire_with({
+                let closed = closed.clone();
+                move || async move {
+                    Ok(TestResource {
+                        id: "old".into(),
+                        healthy: Arc::new(AtomicBool::new(true)),
+                        closed,
+                    })
+                }
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        drop(conn);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert!(
+            closed.load(Ordering::Relaxed),
+            "resource past max_lifetime should be closed on release"
+        );
+        assert_eq!(pool.stats().await.idle, 0);
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_only_returns_idle() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(make_options(), counting_factory(created.clone()));
+
+        assert!(pool.try_acquire().await.is_none(), "nothing idle yet");
+        assert_eq!(created.load(Ordering::SeqCst), 0, "must not create");
+
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let held = pool.try_acquire().await.expect("idle resource available");
+        assert!(pool.try_acquire().await.is_none(), "at capacity");
+        drop(held);
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_or_create() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(make_options(), counting_factory(created.clone()));
+
+        let held = pool
+            .try_acquire_or_create()
+            .await
+            .unwrap()
+            .expect("capacity available, should create");
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+
+        let none = pool.try_acquire_or_create().await.unwrap();
+        assert!(none.is_none(), "no permit available, must not wait");
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn test_try_acquire_does_not_jump_waiters() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(make_options(), counting_factory(created.clone()));
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // A queued acquire that has been woken but not yet taken the free permit.
+        let ticket = WaitTicket::new(&pool.inner, Priority::Normal);
+        assert!(pool.try_acquire().await.is_none());
+        assert!(pool.try_acquire_or_create().await.unwrap().is_none());
+        drop(ticket);
+
+        assert!(pool.try_acquire().await.is_some());
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+    }
+
+    async fn reuse_order(strategy: ReuseStrategy) -> Vec<String> {
+        let mut options = make_options();
+        options.max_connections = 2;
+        options.reuse_strategy = strategy;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+
+        let first = pool.acquire().await.unwrap();
+        let second = pool.acquire().await.unwrap();
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let mut a = pool.try_acquire().await.unwrap();
+        let mut b = pool.try_acquire().await.unwrap();
+        vec![a.resource().resource_id(), b.resource().resource_id()]
+    }
+
+    #[tokio::test]
+    async fn test_reuse_strategy_fifo_and_lifo() {
+        assert_eq!(reuse_order(ReuseStrategy::Fifo).await, ["warm-0", "warm-1"]);
+        assert_eq!(reuse_order(ReuseStrategy::Lifo).await, ["warm-1", "warm-0"]);
+    }
+
+    fn keyed_factory(
+        closed: Arc<AtomicBool>,
+    ) -> impl Fn(&String) -> std::future::Ready<Result<TestResource>> + Send + Sync + 'static {
+        move |key: &String| {
+            std::future::ready(Ok(TestResource {
+                id: key.clone(),
+                healthy: Arc::new(AtomicBool::new(true)),
+                closed: closed.clone(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_creates_per_key() {
+        let options = KeyedPoolOptions {
+            per_key: make_options(),
+            max_total_connections: 4,
+        };
+        let pool = KeyedPool::new(options, keyed_factory(Arc::new(AtomicBool::new(false))));
+
+        let mut a = pool.acquire(&"a".to_string()).await.unwrap();
+        let mut b = pool.acquire(&"b".to_string()).await.unwrap();
+        assert_eq!(a.resource().resource_id(), "a");
+        assert_eq!(b.resource().resource_id(), "b");
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_shares_global_budget() {
+        let options = KeyedPoolOptions {
+            per_key: make_options(),
+            max_total_connections: 1,
+        };
+        let a_closed = Arc::new(AtomicBool::new(false));
+        let pool = KeyedPool::new(options, keyed_factory(a_closed.clone()));
+
+        let held = pool.acquire(&"a".to_string()).await.unwrap();
+        let blocked = pool.acquire(&"b".to_string()).await;
+        assert!(blocked.is_err(), "global budget exhausted");
+
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // "a" is idle; acquiring "b" evicts it to stay within the budget.
+        let mut b = pool.acquire(&"b".to_string()).await.unwrap();
+        assert_eq!(b.resource().resource_id(), "b");
+        assert!(
+            a_closed.load(Ordering::Relaxed),
+            "idle 'a' should be evicted"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_evicts_empty_keys() {
+        let mut per_key = make_options();
+        per_key.idle_timeout = Some(Duration::from_millis(20));
+        per_key.health_check_interval = Duration::from_millis(30);
+        let options = KeyedPoolOptions {
+            per_key,
+            max_total_connections: 4,
+        };
+        let pool = KeyedPool::new(options, keyed_factory(Arc::new(AtomicBool::new(false))));
+
+        drop(pool.acquire(&"a".to_string()).await.unwrap());
+        assert_eq!(pool.len(), 1);
+
+        // Sub-pool maintenance expires the idle resource, then the key goes.
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(pool.is_empty());
+    }
+
+    /// A keyed factory that keeps every resource's `closed` flag.
+    fn tracking_keyed_factory(
+        opened: Arc<std::sync::Mutex<Vec<Arc<AtomicBool>>>>,
+    ) -> impl Fn(&String) -> std::future::Ready<Result<TestResource>> + Send + Sync + 'static {
+        move |key: &String| {
+            let closed = Arc::new(AtomicBool::new(false));
+            opened.lock().unwrap().push(closed.clone());
+            std::future::ready(Ok(TestResource {
+                id: key.clone(),
+                healthy: Arc::new(AtomicBool::new(true)),
+                closed,
+            }))
+        }
+    }
+
+    fn still_open(opened: &std::sync::Mutex<Vec<Arc<AtomicBool>>>) -> usize {
+        let opened = opened.lock().unwrap();
+        opened.iter().filter(|c| !c.load(Ordering::Relaxed)).count()
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_ignores_per_key_min_connections() {
+        let mut per_key = make_options();
+        per_key.min_connections = 3;
+        per_key.max_connections = 3;
+        let options = KeyedPoolOptions {
+            per_key,
+            max_total_connections: 2,
+        };
+        let opened = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let pool = KeyedPool::new(options, tracking_keyed_factory(opened.clone()));
+
+        for key in ["a", "b", "c"] {
+            drop(pool.acquire(&key.to_string()).await.unwrap());
+        }
+        tokio::time::sleep(Duration::from_millis(120)).await;
+
+        assert_eq!(opened.lock().unwrap().len(), 3, "no warm-up creations");
+        assert_eq!(still_open(&opened), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_pool_ignores_replace_before_expire() {
+        let mut per_key = make_options();
+        per_key.health_check = HealthCheckPolicy::Never;
+        per_key.idle_timeout = None;
+        per_key.max_lifetime = Some(Duration::from_secs(30));
+        per_key.health_check_interval = Duration::from_secs(10);
+        per_key.replace_before_expire = true;
+        let options = KeyedPoolOptions {
+            per_key,
+            max_total_connections: 1,
+        };
+        let opened = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let pool = KeyedPool::new(options, tracking_keyed_factory(opened.clone()));
+
+        drop(pool.acquire(&"a".to_string()).await.unwrap());
+        tokio::time::sleep(Duration::from_secs(45)).await;
+
+        // The expired resource is closed without a successor outside the budget.
+        assert_eq!(opened.lock().unwrap().len(), 1);
+        assert_eq!(still_open(&opened), 0);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_concurrent_acquires_stay_within_budget() {
+        let mut per_key = make_options();
+        per_key.health_check = HealthCheckPolicy::Never;
+        per_key.acquire_timeout = Duration::from_secs(5);
+        let options = KeyedPoolOptions {
+            per_key,
+            max_total_connections: 2,
+        };
+        let opened = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let pool = KeyedPool::new(options, tracking_keyed_factory(opened.clone()));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                let pool = pool.clone();
+                let opened = opened.clone();
+                tokio::spawn(async move {
+                    let key = format!("k{}", i % 4);
+                    for _ in 0..5 {
+                        let conn = pool.acquire(&key).await.unwrap();
+                        assert!(still_open(&opened) <= 2, "budget exceeded");
+                        tokio::time::sleep(Duration::from_millis(2)).await;
+                        drop(conn);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(still_open(&opened) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_full_key_does_not_block_other_keys() {
+        let options = KeyedPoolOptions {
+            per_key: make_options(),
+            max_total_connections: 3,
+        };
+        let pool = KeyedPool::new(options, keyed_factory(Arc::new(AtomicBool::new(false))));
+        let held = pool.acquire(&"a".to_string()).await.unwrap();
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move { pool.acquire(&"a".to_string()).await.is_ok() })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let started = Instant::now();
+        let mut b = pool.acquire(&"b".to_string()).await.unwrap();
+        assert_eq!(b.resource().resource_id(), "b");
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        // Waiters on the full key give up after one acquire_timeout.
+        for waiter in waiters {
+            assert!(!waiter.await.unwrap());
+        }
+        assert!(started.elapsed() < Duration::from_millis(250));
+        drop(held);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keyed_pool_reuses_on_different_keys_concurrently() {
+        let mut per_key = make_options();
+        per_key.idle_timeout = None;
+        per_key.health_check_interval = Duration::from_secs(3600);
+        let options = KeyedPoolOptions {
+            per_key,
+            max_total_connections: 5,
+        };
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let pool = KeyedPool::new(options, {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            move |key: &String| {
+                std::future::ready(Ok(SlowCheck {
+                    id: key.clone(),
+                    delay: Duration::from_millis(100),
+                    in_flight: in_flight.clone(),
+                    peak: peak.clone(),
+                }))
+            }
+        });
+        let keys: Vec<_> = (0..5).map(|i| format!("k{i}")).collect();
+        for key in &keys {
+            drop(pool.acquire(key).await.unwrap());
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let started = Instant::now();
+        let reuses = keys.iter().map(|key| pool.acquire(key));
+        for conn in futures::future::join_all(reuses).await {
+            conn.unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_events_report_lifecycle() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(make_options(), counting_factory(created));
+        let mut events = pool.events();
+
+        let held = pool.acquire().await.unwrap();
+        assert!(pool.acquire().await.is_err(), "capacity 1 should time out");
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        pool.close().await;
+
+        let mut kinds = Vec::new();
+        while let Ok(evt) = events.try_recv() {
+            kinds.push(evt.kind);
+        }
+        assert_eq!(
+            kinds,
+            [
+                PoolEventKind::Created,
+                PoolEventKind::AcquireTimeout,
+                PoolEventKind::Released,
+                PoolEventKind::Reused,
+                PoolEventKind::Released,
+                PoolEventKind::Closed,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_events_report_unhealthy_idle() {
+        let pool = Pool::<TestResource>::new(make_options());
+        let healthy = Arc::new(AtomicBool::new(true));
+        drop(
+            pool.acquire_with({
+                let healthy = healthy.clone();
+                move || async move {
+                    Ok(TestResource {
+                        id: "sick".into(),
+                        healthy,
+                        closed: Arc::new(AtomicBool::new(false)),
+                    })
+                }
+            })
+            .await
+            .unwrap(),
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let mut events = pool.events();
+        healthy.store(false, Ordering::Relaxed);
+
+        // Maintenance notices the idle resource is unhealthy.
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        let evt = events.try_recv().expect("health check event");
+        assert_eq!(evt.kind, PoolEventKind::HealthCheckFailed);
+        assert_eq!(evt.resource_id.as_deref(), Some("sick"));
+    }
+
+    #[tokio::test]
+    async fn test_close_gracefully_waits_for_checkouts() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(make_options(), counting_factory(created));
+        let conn = pool.acquire().await.unwrap();
+
+        let closer = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.close_gracefully(Duration::from_millis(500)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!closer.is_finished(), "close should wait for the checkout");
+        assert!(pool.acquire().await.is_err(), "new acquires are rejected");
+
+        drop(conn);
+        let stragglers = tokio::time::timeout(Duration::from_millis(100), closer)
             .await
+            .expect("close should finish once the connection returns")
             .unwrap();
-        assert!(
-            closed_flag.load(Ordering::Relaxed),
-            "old resource should have been closed by cleanup"
-        );
-        drop(new);
+        assert_eq!(stragglers, 0);
+        assert_eq!(pool.stats().await.idle, 0);
     }
 
     #[tokio::test]
-    async fn test_permit_released_after_requeue() {
-        // With max_connections=1, second acquire should wait until first is dropped.
+    async fn test_close_gracefully_reports_stragglers() {
         let pool = Pool::<TestResource>::new(make_options());
-        let healthy = Arc::new(AtomicBool::new(true));
         let closed = Arc::new(AtomicBool::new(false));
-
-        let conn1 = pool
-            .acquire({
-                let healthy = healthy.clone();
+        let conn = pool
+            .acquire_with({
                 let closed = closed.clone();
-                move || {
-                    let healthy = healthy.clone();
-                    let closed = closed.clone();
-                    async move {
-                        Ok(TestResource {
-                            id: "one".into(),
-                            healthy,
-                            closed,
-                        })
-                    }
+                move || async move {
+                    Ok(TestResource {
+                        id: "straggler".into(),
+                        healthy: Arc::new(AtomicBool::new(true)),
+                        closed,
+                    })
                 }
             })
             .await
             .unwrap();
 
-        // Start second acquire which should block until conn1 is dropped
-        let pool2 = pool.clone();
-        let task = tokio::spawn(async move {
-            pool2
-                .acquire(|| async {
-                    Ok(TestResource {
-                        id: "two".into(),
-                        healthy: Arc::new(AtomicBool::new(true)),
-                        closed: Arc::new(AtomicBool::new(false)),
-                    })
+        let stragglers = pool.close_gracefully(Duration::from_millis(30)).await;
+        assert_eq!(stragglers, 1);
+        assert!(
+            !closed.load(Ordering::Relaxed),
+            "the holder still owns the straggler"
+        );
+
+        drop(conn);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(
+            closed.load(Ordering::Relaxed),
+            "straggler is closed when finally dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_counters() {
+        let hooks = PoolHooks::<TestResource> {
+            after_release: Some(Arc::new(
+                |r: &mut TestResource, _meta: PoolConnectionMetadata| {
+                    let keep = r.id != "warm-1";
+                    Box::pin(async move { Ok(keep) })
+                },
+            )),
+            ..Default::default()
+        };
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(make_options())
+            .hooks(hooks)
+            .factory(move || counting_factory(created.clone())())
+            .build();
+
+        let held = pool.acquire().await.unwrap();
+        let stats = pool.stats().await;
+        assert_eq!(stats.active, 1);
+        assert_eq!(stats.total_created, 1);
+
+        // A second acquire has to wait for the first to be returned.
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { drop(pool.acquire().await.unwrap()) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(held);
+        waiter.await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let stats = pool.stats().await;
+        assert_eq!(stats.active, 0);
+        assert_eq!(stats.acquire_waits, 1);
+        assert!(stats.acquire_wait_time >= Duration::from_millis(10));
+        assert_eq!(stats.total_created, 1, "waiter reused warm-0");
+        assert_eq!(stats.total_closed.total(), 0);
+
+        // warm-0 idle times out; the next resource is rejected by after_release.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let stats = pool.stats().await;
+        assert_eq!(stats.total_created, 2);
+        assert_eq!(stats.total_closed.idle_timeout, 1);
+        assert_eq!(stats.total_closed.hook_rejected, 1);
+    }
+
+    async fn served_order(
+        waiters: &[(&'static str, Priority, u64)],
+        options: PoolOptions,
+    ) -> Vec<&'static str> {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+        let held = pool.acquire().await.unwrap();
+
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for &(name, priority, delay_ms) in waiters {
+            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+            let pool = pool.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let conn = pool.acquire_with_priority(priority).await.unwrap();
+                order.lock().unwrap().push(name);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                drop(conn);
+            }));
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(held);
+        for t in tasks {
+            t.await.unwrap();
+        }
+        let order = order.lock().unwrap().clone();
+        order
+    }
+
+    #[tokio::test]
+    async fn test_wait_queue_wakes_only_head_changes() {
+        use futures::FutureExt;
+        let starvation = Duration::from_secs(1);
+        let woken = |n: &Arc<tokio::sync::Notify>| n.notified().now_or_never().is_some();
+        let wakes: Vec<_> = (0..3)
+            .map(|_| Arc::new(tokio::sync::Notify::new()))
+            .collect();
+        let mut queue = WaitQueue::default();
+
+        let normal = queue.push(Priority::Normal, wakes[0].clone());
+        queue.refresh(starvation);
+        assert!(woken(&wakes[0]), "first waiter becomes head");
+
+        queue.push(Priority::Low, wakes[1].clone());
+        queue.refresh(starvation);
+        assert!(!woken(&wakes[0]) && !woken(&wakes[1]), "head unchanged");
+
+        let high = queue.push(Priority::High, wakes[2].clone());
+        queue.refresh(starvation);
+        assert!(woken(&wakes[0]), "outranked head steps back");
+        assert!(woken(&wakes[2]), "new head takes over");
+        assert!(!woken(&wakes[1]));
+
+        queue.remove(high, Priority::High);
+        queue.refresh(starvation);
+        assert!(woken(&wakes[0]) && !woken(&wakes[1]));
+        queue.remove(normal, Priority::Normal);
+        queue.refresh(starvation);
+        assert!(woken(&wakes[1]));
+    }
+
+    #[tokio::test]
+    async fn test_acquire_with_priority_serves_higher_first() {
+        let order = served_order(
+            &[
+                ("low", Priority::Low, 0),
+                ("normal", Priority::Normal, 5),
+                ("high", Priority::High, 5),
+                ("low-2", Priority::Low, 5),
+            ],
+            make_options(),
+        )
+        .await;
+        assert_eq!(order, ["high", "normal", "low", "low-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_with_priority_starvation_guard() {
+        let mut options = make_options();
+        options.starvation_timeout = Duration::from_millis(50);
+        let order = served_order(
+            &[("low", Priority::Low, 0), ("high", Priority::High, 80)],
+            options,
+        )
+        .await;
+        assert_eq!(
+            order,
+            ["low", "high"],
+            "low waited past the starvation timeout"
+        );
+    }
+
+    /// Release a resource, mark it unhealthy, then acquire again; returns whether
+    /// the unhealthy resource was handed back out.
+    async fn reused_when_unhealthy(policy: HealthCheckPolicy, idle_for: Duration) -> bool {
+        let mut options = make_options();
+        options.health_check = policy;
+        options.health_check_interval = Duration::from_secs(60);
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created.clone()));
+
+        let mut conn = pool.acquire().await.unwrap();
+        let healthy = conn.resource().healthy.clone();
+        drop(conn);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // Fails only checks that happen after release.
+        healthy.store(false, Ordering::SeqCst);
+        tokio::time::sleep(idle_for).await;
+
+        let mut conn = pool.acquire().await.unwrap();
+        conn.resource().id == "warm-0"
+    }
+
+    #[tokio::test]
+    async fn test_health_check_policy() {
+        assert!(!reused_when_unhealthy(HealthCheckPolicy::Always, Duration::ZERO).await);
+        assert!(reused_when_unhealthy(HealthCheckPolicy::Never, Duration::ZERO).await);
+
+        let older_than = HealthCheckPolicy::OnIdleOlderThan(Duration::from_millis(50));
+        assert!(reused_when_unhealthy(older_than.clone(), Duration::ZERO).await);
+        assert!(!reused_when_unhealthy(older_than, Duration::from_millis(80)).await);
+
+        let asked = Arc::new(AtomicUsize::new(0));
+        let custom = HealthCheckPolicy::Custom(Arc::new({
+            let asked = asked.clone();
+            move |_meta: PoolConnectionMetadata| {
+                asked.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async { false })
+            }
+        }));
+        assert!(reused_when_unhealthy(custom, Duration::ZERO).await);
+        assert_eq!(
+            asked.load(Ordering::SeqCst),
+            2,
+            "asked on release and on reuse"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leak_detection_counts_long_held_connections() {
+        let mut options = make_options();
+        options.max_connections = 2;
+        options.leak_timeout = Some(Duration::from_millis(60));
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+
+        let held = pool.acquire().await.unwrap();
+        let short = pool.acquire().await.unwrap();
+        drop(short);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        // Reported once, however many ticks see it.
+        assert_eq!(pool.stats().await.leaked, 1);
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(pool.stats().await.leaked, 1);
+    }
+
+    #[tokio::test]
+    async fn test_detach_takes_resource_and_frees_capacity() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(make_options(), counting_factory(created));
+        let mut events = pool.events();
+
+        let conn = pool.acquire().await.unwrap();
+        let detached = conn.detach();
+        assert_eq!(detached.id, "warm-0");
+        assert!(!detached.closed.load(Ordering::SeqCst));
+
+        // Capacity is free immediately and the resource never went back to idle.
+        let mut next = pool.try_acquire_or_create().await.unwrap().unwrap();
+        assert_eq!(next.resource().id, "warm-1");
+        let stats = pool.stats().await;
+        assert_eq!(stats.active, 1);
+
+        assert_eq!(events.recv().await.unwrap().kind, PoolEventKind::Created);
+        assert_eq!(events.recv().await.unwrap().kind, PoolEventKind::Detached);
+    }
+
+    #[tokio::test]
+    async fn test_release_requeues_inline_when_no_async_work() {
+        let mut options = make_options();
+        options.health_check = HealthCheckPolicy::Never;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+
+        // No health check or hook: the resource is idle as soon as drop returns.
+        drop(pool.acquire().await.unwrap());
+        let mut conn = pool.try_acquire().await.expect("requeued synchronously");
+        assert_eq!(conn.resource().id, "warm-0");
+        drop(conn);
+        assert_eq!(pool.stats().await.active, 0);
+    }
+
+    #[tokio::test]
+    async fn test_release_queue_preserves_drop_order() {
+        let mut options = make_options();
+        options.max_connections = 3;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+
+        let a = pool.acquire().await.unwrap();
+        let b = pool.acquire().await.unwrap();
+        let c = pool.acquire().await.unwrap();
+        // Health checks on release go through the maintenance task's queue.
+        drop(c);
+        drop(a);
+        drop(b);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let mut order = Vec::new();
+        let mut held = Vec::new();
+        while let Some(mut conn) = pool.try_acquire().await {
+            order.push(conn.resource().id.clone());
+            held.push(conn);
+        }
+        assert_eq!(order, ["warm-2", "warm-0", "warm-1"]);
+    }
+
+    /// Factory that fails `failures` times before succeeding; counts calls.
+    fn flaky_factory(failures: usize, calls: Arc<AtomicUsize>) -> ResourceFactory<TestResource> {
+        Arc::new(move || {
+            let calls = calls.clone();
+            Box::pin(async move {
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                if n < failures {
+                    return Err(ShadowcatError::Protocol("upstream unavailable".into()));
+                }
+                Ok(TestResource {
+                    id: format!("flaky-{n}"),
+                    healthy: Arc::new(AtomicBool::new(true)),
+                    closed: Arc::new(AtomicBool::new(false)),
                 })
-                .await
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_create_retry_with_backoff() {
+        let mut options = make_options();
+        options.create_retry = Some(RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+            jitter: false,
         });
 
-        // Give it a moment to attempt acquire (should be pending)
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options.clone(), flaky_factory(2, calls.clone()));
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(conn.resource().id, "flaky-2");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options.clone(), flaky_factory(3, calls.clone()));
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(
+            calls.load(Ordering::SeqCst),
+            3,
+            "gives up after max_attempts"
+        );
+
+        // A retry that would start after acquire_timeout is not attempted.
+        options.create_retry.as_mut().unwrap().backoff = Duration::from_millis(500);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, flaky_factory(1, calls.clone()));
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let mut policy = RetryPolicy {
+            max_attempts: 5,
+            backoff: Duration::from_millis(100),
+            jitter: false,
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        policy.jitter = true;
+        let d = policy.delay(3);
+        assert!(d >= Duration::from_millis(200) && d <= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_and_recovers() {
+        let mut options = make_options();
+        options.circuit_breaker = Some(CircuitBreakerOptions {
+            failure_threshold: 2,
+            cool_down: Duration::from_millis(100),
+            half_open_probes: 1,
+        });
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, flaky_factory(2, calls.clone()));
+        let mut events = pool.events();
+
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(pool.stats().await.circuit, Some(CircuitState::Closed));
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(pool.stats().await.circuit, Some(CircuitState::Open));
+
+        // Open: fails fast without calling the factory.
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(conn.resource().id, "flaky-2");
+        assert_eq!(pool.stats().await.circuit, Some(CircuitState::Closed));
+
+        let mut transitions = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let PoolEventKind::CircuitStateChanged(state) = event.kind {
+                transitions.push(state);
+            }
+        }
+        assert_eq!(
+            transitions,
+            [
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_failure_reopens() {
+        let mut options = make_options();
+        options.circuit_breaker = Some(CircuitBreakerOptions {
+            failure_threshold: 1,
+            cool_down: Duration::from_millis(50),
+            half_open_probes: 1,
+        });
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, flaky_factory(2, calls.clone()));
+
+        assert!(pool.acquire().await.is_err());
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        // The probe fails, so the breaker reopens for another cool-down.
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(pool.stats().await.circuit, Some(CircuitState::Open));
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_open_still_reuses_idle() {
+        let mut options = make_options();
+        options.max_connections = 2;
+        options.circuit_breaker = Some(CircuitBreakerOptions {
+            failure_threshold: 1,
+            cool_down: Duration::from_secs(60),
+            half_open_probes: 1,
+        });
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, flaky_factory(usize::MAX, calls.clone()));
+
+        let held = pool
+            .acquire_with(|| async {
+                Ok(TestResource {
+                    id: "healthy".into(),
+                    healthy: Arc::new(AtomicBool::new(true)),
+                    closed: Arc::new(AtomicBool::new(false)),
+                })
+            })
+            .await
+            .unwrap();
+        assert!(pool.acquire().await.is_err());
+        assert_eq!(pool.stats().await.circuit, Some(CircuitState::Open));
+
+        drop(held);
         tokio::time::sleep(Duration::from_millis(20)).await;
-        assert!(!task.is_finished());
 
-        drop(conn1); // triggers return-to-idle and then releases permit
-        let res = tokio::time::timeout(Duration::from_millis(300), task).await;
-        assert!(
-            res.is_ok(),
-            "second acquire should complete after first drop"
+        // Open only blocks creation; the healthy idle resource is still handed out.
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(conn.resource().id, "healthy");
+        assert!(pool.acquire().await.is_err(), "nothing idle: fails fast");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_paused_clock_drives_expiry_deterministically() {
+        let mut options = make_options();
+        options.health_check = HealthCheckPolicy::Never;
+        options.idle_timeout = Some(Duration::from_secs(30));
+        options.health_check_interval = Duration::from_secs(3600);
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+        let mut events = pool.events();
+
+        drop(pool.acquire().await.unwrap());
+        tokio::time::advance(Duration::from_secs(29)).await;
+        pool.run_maintenance_now().await;
+        assert_eq!(pool.stats().await.idle, 1);
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        pool.run_maintenance_now().await;
+        assert_eq!(pool.stats().await.idle, 0);
+
+        let kinds: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                PoolEventKind::Created,
+                PoolEventKind::Released,
+                PoolEventKind::Expired(ExpiryReason::IdleTimeout)
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replace_before_expire_creates_successor_first() {
+        let mut options = make_options();
+        options.health_check = HealthCheckPolicy::Never;
+        options.idle_timeout = None;
+        options.max_lifetime = Some(Duration::from_secs(60));
+        options.health_check_interval = Duration::from_secs(3600);
+        options.replace_before_expire = true;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::new_with_factory(options, counting_factory(created));
+
+        drop(pool.acquire().await.unwrap());
+        let mut events = pool.events();
+        tokio::time::advance(Duration::from_secs(61)).await;
+        pool.run_maintenance_now().await;
+
+        let events: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|e| (e.kind, e.resource_id.unwrap()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (PoolEventKind::Created, "warm-1".to_string()),
+                (
+                    PoolEventKind::Expired(ExpiryReason::MaxLifetime),
+                    "warm-0".to_string()
+                ),
+            ]
+        );
+        let mut conn = pool.try_acquire().await.unwrap();
+        assert_eq!(conn.resource().id, "warm-1");
+    }
+
+    #[derive(Default)]
+    struct CountingRecorder {
+        acquired: AtomicUsize,
+        waits: AtomicUsize,
+        timeouts: AtomicUsize,
+        created: AtomicUsize,
+        create_failed: AtomicUsize,
+        released: AtomicUsize,
+        gauges: std::sync::Mutex<Option<(usize, usize)>>,
+    }
+
+    impl PoolMetricsRecorder for CountingRecorder {
+        fn acquired(&self) {
+            self.acquired.fetch_add(1, Ordering::SeqCst);
+        }
+        fn acquire_wait(&self, _wait: Duration) {
+            self.waits.fetch_add(1, Ordering::SeqCst);
+        }
+        fn acquire_timed_out(&self) {
+            self.timeouts.fetch_add(1, Ordering::SeqCst);
+        }
+        fn created(&self) {
+            self.created.fetch_add(1, Ordering::SeqCst);
+        }
+        fn create_failed(&self) {
+            self.create_failed.fetch_add(1, Ordering::SeqCst);
+        }
+        fn released(&self) {
+            self.released.fetch_add(1, Ordering::SeqCst);
+        }
+        fn connections(&self, idle: usize, active: usize) {
+            *self.gauges.lock().unwrap() = Some((idle, active));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_metrics_recorder_sees_lifecycle() {
+        let mut options = make_options();
+        options.health_check = HealthCheckPolicy::Never;
+        let recorder = Arc::new(CountingRecorder::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let factory = flaky_factory(1, calls);
+        let pool = Pool::builder()
+            .options(options)
+            .factory(move || factory())
+            .metrics(recorder.clone())
+            .build();
+
+        assert!(pool.acquire().await.is_err());
+        let held = pool.acquire().await.unwrap();
+        assert!(pool.acquire().await.is_err(), "times out at capacity");
+        pool.run_maintenance_now().await;
+        assert_eq!(*recorder.gauges.lock().unwrap(), Some((0, 1)));
+        drop(held);
+
+        assert_eq!(recorder.create_failed.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.created.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.acquired.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.waits.load(Ordering::SeqCst), 2);
+        assert_eq!(recorder.timeouts.load(Ordering::SeqCst), 1);
+        assert_eq!(recorder.released.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_pool_records_metrics_per_key() {
+        let recorders: Arc<std::sync::Mutex<HashMap<String, Arc<CountingRecorder>>>> =
+            Arc::default();
+        let metrics: KeyedMetricsFactory<String> = {
+            let recorders = recorders.clone();
+            Arc::new(move |key: &String| {
+                let recorder = Arc::new(CountingRecorder::default());
+                recorders
+                    .lock()
+                    .unwrap()
+                    .insert(key.clone(), recorder.clone());
+                recorder
+            })
+        };
+        let options = KeyedPoolOptions {
+            per_key: make_options(),
+            max_total_connections: 4,
+        };
+        let pool = KeyedPool::new_with_metrics(
+            options,
+            keyed_factory(Arc::new(AtomicBool::new(false))),
+            metrics,
+        );
+
+        drop(pool.acquire(&"a".to_string()).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(pool.acquire(&"a".to_string()).await.unwrap());
+        drop(pool.acquire(&"b".to_string()).await.unwrap());
+
+        let recorders = recorders.lock().unwrap();
+        let (a, b) = (&recorders["a"], &recorders["b"]);
+        assert_eq!(a.created.load(Ordering::SeqCst), 1);
+        assert_eq!(a.acquired.load(Ordering::SeqCst), 2);
+        assert_eq!(a.waits.load(Ordering::SeqCst), 2);
+        assert_eq!(b.created.load(Ordering::SeqCst), 1);
+        assert_eq!(b.acquired.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_crate_recorder_names_and_labels() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let debugging = DebuggingRecorder::new();
+        let snapshotter = debugging.snapshotter();
+        let recorder = MetricsRecorder::new("api.example.com:443");
+        metrics::with_local_recorder(&debugging, || {
+            recorder.created();
+            recorder.created();
+            recorder.expired(ExpiryReason::IdleTimeout);
+            recorder.acquire_wait(Duration::from_millis(250));
+            recorder.connections(3, 1);
+        });
+
+        let recorded: HashMap<_, _> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let labels: Vec<_> = key
+                    .key()
+                    .labels()
+                    .map(|l| format!("{}={}", l.key(), l.value()))
+                    .collect();
+                (key.key().name().to_string(), (labels, value))
+            })
+            .collect();
+        let upstream = "upstream=api.example.com:443".to_string();
+        assert_eq!(
+            recorded["shadowcat_pool_created_total"],
+            (vec![upstream.clone()], DebugValue::Counter(2))
+        );
+        assert_eq!(
+            recorded["shadowcat_pool_expired_total"].0,
+            [upstream.clone(), "reason=idle_timeout".to_string()]
         );
+        assert!(matches!(
+            &recorded["shadowcat_pool_acquire_wait_seconds"].1,
+            DebugValue::Histogram(values) if values.len() == 1 && values[0].into_inner() == 0.25
+        ));
+        assert!(matches!(
+            recorded["shadowcat_pool_idle_connections"].1,
+            DebugValue::Gauge(v) if v.into_inner() == 3.0
+        ));
+        assert!(matches!(
+            recorded["shadowcat_pool_active_connections"].1,
+            DebugValue::Gauge(v) if v.into_inner() == 1.0
+        ));
     }
 
     #[tokio::test]
-    async fn test_acquire_cancels_on_close() {
-        // With max_connections=1, second acquire should be pending; closing should cancel it promptly.
-        let pool = Pool::<TestResource>::new(make_options());
-        let healthy = Arc::new(AtomicBool::new(true));
-        let closed = Arc::new(AtomicBool::new(false));
+    async fn test_on_hook_error_reports_failures() {
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hooks = PoolHooks::<TestResource> {
+            before_acquire: Some(Arc::new(
+                |_r: &mut TestResource, _meta: PoolConnectionMetadata| {
+                    Box::pin(async { Err(ShadowcatError::Protocol("ping failed".into())) })
+                },
+            )),
+            after_release: Some(Arc::new(
+                |r: &mut TestResource, _meta: PoolConnectionMetadata| {
+                    // A plain rejection is not an error and is not reported.
+                    let keep = r.id != "warm-0";
+                    Box::pin(async move { Ok(keep) })
+                },
+            )),
+            on_hook_error: Some(Arc::new({
+                let reported = reported.clone();
+                move |e: &HookError<'_>| {
+                    reported.lock().unwrap().push((
+                        e.hook,
+                        e.resource_id.clone(),
+                        e.error.to_string(),
+                    ));
+                }
+            })),
+            ..Default::default()
+        };
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(make_options())
+            .hooks(hooks)
+            .factory(move || counting_factory(created.clone())())
+            .build();
+
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(reported.lock().unwrap().is_empty());
+
+        // warm-1 goes idle, then fails before_acquire on reuse.
+        drop(pool.acquire().await.unwrap());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let mut conn = pool.acquire().await.unwrap();
+        assert_eq!(conn.resource().id, "warm-2");
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].0, PoolHook::BeforeAcquire);
+        assert_eq!(reported[0].1, "warm-1");
+        assert!(reported[0].2.contains("ping failed"));
+    }
+
+    /// Hook that parks long enough for the caller to cancel mid-hook.
+    fn slow_hook() -> HookBool<TestResource> {
+        Arc::new(|_r: &mut TestResource, _meta: PoolConnectionMetadata| {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok(true)
+            })
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_acquire_requeues_idle_resource() {
+        let hooks = PoolHooks::<TestResource> {
+            before_acquire: Some(slow_hook()),
+            ..Default::default()
+        };
+        let mut options = make_options();
+        options.health_check = HealthCheckPolicy::Never;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(options)
+            .hooks(hooks)
+            .factory(move || counting_factory(created.clone())())
+            .build();
+        let mut conn = pool.acquire().await.unwrap();
+        let closed = conn.resource().closed.clone();
+        drop(conn);
+
+        tokio::select! {
+            _ = pool.acquire() => panic!("before_acquire should still be running"),
+            _ = tokio::time::sleep(Duration::from_millis(50)) => {}
+        }
+
+        let stats = pool.stats().await;
+        assert_eq!(stats.idle, 1, "resource went back to idle");
+        assert_eq!(stats.active, 0);
+        assert_eq!(pool.inner.semaphore.available_permits(), 1);
+        assert!(!closed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_acquire_keeps_idle_time() {
+        let hooks = PoolHooks::<TestResource> {
+            before_acquire: Some(slow_hook()),
+            ..Default::default()
+        };
+        let mut options = make_options();
+        options.health_check = HealthCheckPolicy::Never;
+        options.idle_timeout = Some(Duration::from_secs(30));
+        options.health_check_interval = Duration::from_secs(3600);
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(options)
+            .hooks(hooks)
+            .factory(move || counting_factory(created.clone())())
+            .build();
+        drop(pool.acquire().await.unwrap());
+        tokio::time::advance(Duration::from_secs(25)).await;
+
+        tokio::select! {
+            _ = pool.acquire() => panic!("before_acquire should still be running"),
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+        assert_eq!(pool.stats().await.idle, 1);
 
-        // Hold first connection to exhaust capacity
-        let _conn = pool
-            .acquire({
-                let healthy = healthy.clone();
+        // Idle for 31s in total: the cancelled claim did not restart the clock.
+        tokio::time::advance(Duration::from_secs(5)).await;
+        pool.run_maintenance_now().await;
+        assert_eq!(pool.stats().await.idle, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_acquire_closes_new_resource() {
+        let hooks = PoolHooks::<TestResource> {
+            after_create: Some(Arc::new(
+                |_r: &mut TestResource, _meta: PoolConnectionMetadata| {
+                    Box::pin(async {
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                        Ok(())
+                    })
+                },
+            )),
+            ..Default::default()
+        };
+        let closed = Arc::new(AtomicBool::new(false));
+        let pool = Pool::builder()
+            .options(make_options())
+            .hooks(hooks)
+            .factory({
                 let closed = closed.clone();
                 move || {
-                    let healthy = healthy.clone();
                     let closed = closed.clone();
                     async move {
                         Ok(TestResource {
-                            id: "held".into(),
-                            healthy,
+                            id: "new".into(),
+                            healthy: Arc::new(AtomicBool::new(true)),
                             closed,
                         })
                     }
                 }
             })
-            .await
-            .unwrap();
-
-        // Start a second acquire that will block on the semaphore
-        let pool2 = pool.clone();
-        let pending = tokio::spawn(async move {
-            pool2
-                .acquire(|| async { unreachable!("should not construct while closed") })
-                .await
-        });
-
-        // Ensure it's pending
-        tokio::time::sleep(Duration::from_millis(20)).await;
-        assert!(!pending.is_finished());
-
-        // Close the pool; this should cancel the pending acquire promptly
-        let pool3 = pool.clone();
-        let closer = tokio::spawn(async move { pool3.close().await });
+            .build();
 
-        let res = tokio::time::timeout(Duration::from_millis(200), pending).await;
-        assert!(
-            res.is_ok(),
-            "pending acquire should resolve promptly after close starts"
-        );
-        let join = res.unwrap();
-        assert!(join.is_ok(), "task should not panic");
-        let inner = join.unwrap();
-        assert!(inner.is_err(), "acquire should error due to pool close");
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), pool.acquire()).await;
+        assert!(cancelled.is_err());
+        // Maintenance closes the abandoned resource.
+        tokio::task::yield_now().await;
+        tokio::time::sleep(Duration::from_millis(1)).await;
 
-        // Ensure close completes
-        let _ = closer.await;
+        assert!(closed.load(Ordering::SeqCst));
+        let stats = pool.stats().await;
+        assert_eq!((stats.idle, stats.active), (0, 0));
+        assert_eq!(pool.inner.semaphore.available_permits(), 1);
     }
 
-    #[tokio::test]
-    async fn test_close_event_notifies_on_close() {
-        let pool = Pool::<TestResource>::new(make_options());
-        let evt = pool.close_event();
+    /// Resource whose health check takes `delay`, counting checks in flight.
+    struct SlowCheck {
+        id: String,
+        delay: Duration,
+        in_flight: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
 
-        let (armed_tx, armed_rx) = tokio::sync::oneshot::channel();
+    #[async_trait]
+    impl PoolableResource for SlowCheck {
+        async fn is_healthy(&self) -> bool {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            true
+        }
 
-        // Construct the owned waiter first; then signal we're armed; then await it.
-        let waiter = tokio::spawn(async move {
-            let fut = evt.notified();
-            let _ = armed_tx.send(());
-            fut.await;
-        });
+        async fn close(&mut self) -> crate::error::Result<()> {
+            Ok(())
+        }
 
-        // Wait until the waiter has created the future, so we can't miss the notify.
-        let _ = armed_rx.await;
+        fn resource_id(&self) -> String {
+            self.id.clone()
+        }
+    }
 
-        // Now close can't race - the waiter is registered (or will complete immediately).
-        pool.close().await;
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_health_checks_concurrently_with_timeout() {
+        let mut options = make_options();
+        options.max_connections = 5;
+        options.idle_timeout = None;
+        options.health_check_interval = Duration::from_secs(3600);
+        options.health_check_concurrency = 2;
+        options.health_check_timeout = Some(Duration::from_secs(1));
+        let pool = Pool::<SlowCheck>::new(options);
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
 
-        let done = tokio::time::timeout(Duration::from_millis(300), waiter).await;
-        assert!(
-            done.is_ok(),
-            "close_event waiter should complete after close"
+        let mut held = Vec::new();
+        for (i, delay) in [1, 1, 1, 1, 5000].into_iter().enumerate() {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            held.push(
+                pool.acquire_with(move || async move {
+                    Ok(SlowCheck {
+                        id: format!("r{i}"),
+                        delay: Duration::from_millis(delay),
+                        in_flight,
+                        peak,
+                    })
+                })
+                .await
+                .unwrap(),
+            );
+        }
+        drop(held);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(
+            pool.stats().await.idle,
+            4,
+            "hung check timed out on release"
         );
+
+        // The timed-out check was dropped mid-sleep and never decremented.
+        in_flight.store(0, Ordering::SeqCst);
+        peak.store(0, Ordering::SeqCst);
+        let started = Instant::now();
+        pool.run_maintenance_now().await;
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_millis(4));
+        assert_eq!(pool.stats().await.idle, 4);
     }
 
-    #[tokio::test]
-    async fn test_pop_idle_filters_and_closes_stale() {
-        let options = PoolOptions {
-            max_connections: 1,
-            acquire_timeout: Duration::from_millis(200),
-            idle_timeout: Some(Duration::from_millis(30)),
-            max_lifetime: Some(Duration::from_secs(60)),
-            health_check_interval: Duration::from_millis(500),
-        };
-        let pool = Pool::<TestResource>::new(options);
-        let healthy = Arc::new(AtomicBool::new(true));
-        let stale_closed = Arc::new(AtomicBool::new(false));
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_leaves_unchecked_idle_reusable() {
+        let mut options = make_options();
+        options.max_connections = 4;
+        options.idle_timeout = None;
+        options.health_check_interval = Duration::from_secs(3600);
+        options.health_check_concurrency = 2;
+        let pool = Pool::<SlowCheck>::new(options);
+        let (in_flight, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
 
-        // Create one resource and drop to idle
-        {
-            let _c = pool
-                .acquire({
-                    let healthy = healthy.clone();
-                    let stale_closed = stale_closed.clone();
-                    move || {
-                        let healthy = healthy.clone();
-                        let stale_closed = stale_closed.clone();
-                        async move {
-                            Ok(TestResource {
-                                id: "old".into(),
-                                healthy,
-                                closed: stale_closed,
-                            })
-                        }
-                    }
+        let mut held = Vec::new();
+        for i in 0..4 {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            held.push(
+                pool.acquire_with(move || async move {
+                    Ok(SlowCheck {
+                        id: format!("r{i}"),
+                        delay: Duration::from_secs(1),
+                        in_flight,
+                        peak,
+                    })
                 })
                 .await
-                .unwrap();
+                .unwrap(),
+            );
         }
+        drop(held);
+        // Releases are health-checked two at a time too.
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(pool.stats().await.idle, 4);
 
-        // Sleep past idle_timeout but before maintenance runs
-        tokio::time::sleep(Duration::from_millis(60)).await;
+        let maintenance = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run_maintenance_now().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(pool.stats().await.idle, 2, "only one batch is out");
 
-        // Next acquire should filter the stale idle (and close it) and create new
         let mut conn = pool
-            .acquire({
-                let healthy = Arc::new(AtomicBool::new(true));
-                let new_closed = Arc::new(AtomicBool::new(false));
-                move || {
-                    let healthy = healthy.clone();
-                    let new_closed = new_closed.clone();
-                    async move {
-                        Ok(TestResource {
-                            id: "new".into(),
-                            healthy,
-                            closed: new_closed,
-                        })
-                    }
-                }
-            })
+            .acquire_with(|| async { Err(ShadowcatError::Protocol("factory called".into())) })
+            .await
+            .expect("an unchecked idle resource is reused");
+        assert_eq!(conn.resource().id, "r2");
+        maintenance.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_release_does_not_stall_other_returns() {
+        let mut options = make_options();
+        options.max_connections = 2;
+        options.acquire_timeout = Duration::from_secs(1);
+        let pool = Pool::<SlowCheck>::new(options);
+        let counters = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let checked = |id: &str, delay: Duration| {
+            let (in_flight, peak) = counters.clone();
+            let id = id.to_string();
+            move || async move {
+                Ok(SlowCheck {
+                    id,
+                    delay,
+                    in_flight,
+                    peak,
+                })
+            }
+        };
+
+        let slow = pool
+            .acquire_with(checked("slow", Duration::from_secs(2)))
+            .await
+            .unwrap();
+        let fast = pool
+            .acquire_with(checked("fast", Duration::ZERO))
             .await
             .unwrap();
+        drop(slow);
+        drop(fast);
 
-        assert_eq!(conn.resource().resource_id(), "new");
-        assert!(
-            stale_closed.load(Ordering::Relaxed),
-            "stale idle should have been closed by pop_idle_healthy"
-        );
+        // The slow release holds only its own permit; "fast" is reusable at once.
+        let mut conn = pool
+            .acquire_with(checked("new", Duration::ZERO))
+            .await
+            .unwrap();
+        assert_eq!(conn.resource().id, "fast");
     }
 
     #[tokio::test]
-    async fn test_before_acquire_rejects_idle_and_creates_new() {
-        // Prepare a pool with before_acquire hook that rejects id == "bad"
+    async fn test_acquire_matching_filters_idle_by_label() {
         let hooks = PoolHooks::<TestResource> {
-            after_create: None,
-            before_acquire: Some(Arc::new(
-                |r: &mut TestResource, _meta: PoolConnectionMetadata| {
-                    let id = r.id.clone();
-                    Box::pin(async move { Ok(id != "bad") })
-                },
-            )),
-            after_release: None,
+            labels: Some(Arc::new(|r: &TestResource| {
+                let version = if r.id == "warm-0" {
+                    "2025-06-18"
+                } else {
+                    "2025-03-26"
+                };
+                Labels::from([("protocol_version".to_string(), version.to_string())])
+            })),
+            ..Default::default()
         };
-        let pool = Pool::<TestResource>::new_with_hooks(make_options(), hooks);
+        let mut options = make_options();
+        options.max_connections = 3;
+        let created = Arc::new(AtomicUsize::new(0));
+        let pool = Pool::builder()
+            .options(options)
+            .hooks(hooks)
+            .factory(move || counting_factory(created.clone())())
+            .build();
 
-        let healthy = Arc::new(AtomicBool::new(true));
-        let bad_closed = Arc::new(AtomicBool::new(false));
-        let good_closed = Arc::new(AtomicBool::new(false));
+        let first = pool.acquire().await.unwrap();
+        let mut second = pool.acquire().await.unwrap();
+        second.set_label("region", "eu");
+        drop(second);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(20)).await;
 
-        // First acquire a BAD resource and drop to idle
-        {
-            let _c = pool
-                .acquire({
-                    let healthy = healthy.clone();
-                    let bad_closed = bad_closed.clone();
-                    move || {
-                        let healthy = healthy.clone();
-                        let bad_closed = bad_closed.clone();
-                        async move {
-                            Ok(TestResource {
-                                id: "bad".into(),
-                                healthy,
-                                closed: bad_closed,
-                            })
-                        }
-                    }
-                })
-                .await
-                .unwrap();
-        }
+        // FIFO would hand out warm-1 first; the filter skips it.
+        let wants =
+            |l: &Labels| l.get("protocol_version").map(String::as_str) == Some("2025-06-18");
+        let mut conn = pool.acquire_matching(wants).await.unwrap();
+        assert_eq!(conn.resource().id, "warm-0");
 
-        // Now acquire again; hook should reject idle "bad" and factory creates "good"
-        let mut conn = pool
-            .acquire({
-                let healthy = healthy.clone();
-                let good_closed = good_closed.clone();
-                move || {
-                    let healthy = healthy.clone();
-                    let good_closed = good_closed.clone();
-                    async move {
-                        Ok(TestResource {
-                            id: "good".into(),
-                            healthy,
-                            closed: good_closed,
-                        })
-                    }
-                }
-            })
+        let mut eu = pool
+            .acquire_matching(|l: &Labels| l.contains_key("region"))
             .await
-            .expect("acquire should succeed with new resource after rejection");
-        assert_eq!(conn.resource().resource_id(), "good");
-        assert!(
-            bad_closed.load(Ordering::Relaxed),
-            "rejected idle resource should be closed"
-        );
+            .unwrap();
+        assert_eq!(eu.resource().id, "warm-1");
+        assert_eq!(eu.labels()["protocol_version"], "2025-03-26");
+
+        // Nothing idle matches: a new resource is created.
+        let mut fresh = pool
+            .acquire_matching(|l: &Labels| l.contains_key("missing"))
+            .await
+            .unwrap();
+        assert_eq!(fresh.resource().id, "warm-2");
     }
 
     #[tokio::test]
-    async fn test_after_release_rejects_return() {
-        // Hook that closes on release (return false)
-        let hooks = PoolHooks::<TestResource> {
-            after_create: None,
-            before_acquire: None,
-            after_release: Some(Arc::new(
-                |_r: &mut TestResource, _meta: PoolConnectionMetadata| {
-                    Box::pin(async move { Ok(false) })
-                },
-            )),
-        };
-        let pool = Pool::<TestResource>::new_with_hooks(make_options(), hooks);
-
-        let healthy = Arc::new(AtomicBool::new(true));
-        let closed_a = Arc::new(AtomicBool::new(false));
+    async fn test_acquire_matching_stays_within_max_connections() {
+        let mut options = make_options();
+        options.max_connections = 2;
+        let opened = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let factory = tracking_keyed_factory(opened.clone());
+        let pool = Pool::builder()
+            .options(options)
+            .factory(move || factory(&"r".to_string()))
+            .build();
 
-        // Acquire and drop; after_release should cause close instead of requeue
-        {
-            let _c = pool
-                .acquire({
-                    let healthy = healthy.clone();
-                    let closed_a = closed_a.clone();
-                    move || {
-                        let healthy = healthy.clone();
-                        let closed_a = closed_a.clone();
-                        async move {
-                            Ok(TestResource {
-                                id: "a".into(),
-                                healthy,
-                                closed: closed_a,
-                            })
-                        }
-                    }
-                })
-                .await
-                .unwrap();
+        let _held = pool.acquire().await.unwrap();
+        for _ in 0..5 {
+            // No idle resource ever matches, so each call creates.
+            drop(
+                pool.acquire_matching(|l: &Labels| l.contains_key("missing"))
+                    .await
+                    .unwrap(),
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let open = still_open(&opened);
+            assert!(open <= 2, "{open} resources open with max_connections 2");
         }
-        // Give drop task time to run
-        tokio::time::sleep(Duration::from_millis(20)).await;
-        let stats = pool.stats().await;
-        assert_eq!(stats.idle, 0, "resource should not be returned to idle");
-        assert!(
-            closed_a.load(Ordering::Relaxed),
-            "resource should be closed by after_release"
-        );
+        assert_eq!(opened.lock().unwrap().len(), 6);
     }
 }